@@ -166,4 +166,10 @@
 
   window.codexWindowType = WINDOW_TYPE;
   window.electronBridge = electronBridge;
+
+  // Host events (terminal output, app-server status) can arrive before the
+  // view sends its first message, so listen from the start.
+  void ensureAppMessageSubscription().catch((error) => {
+    console.error("[electronBridgeCompat] failed to subscribe to app messages", error);
+  });
 })();
@@ -1,10 +1,15 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use app_server_bridge::AppServerBridge;
+use app_server_bridge::{
+    AppServerBridge, AppServerTransport, NotificationEvent, NotificationFilter, RequestCancelled,
+    ShutdownPolicy, TimeoutPolicy,
+};
+use base64::Engine;
 use git_worker::GitWorkerService;
 use host_api::{
     dispatch_registry, is_known_mutation_method, is_known_query_method, parse_deep_link,
-    DeepLinkRoute, HostMutationRequest, HostQueryRequest, HostResponse, WindowType, WorkerRequest,
+    DeepLinkRoute, HostEvent, HostMutationRequest, HostQueryRequest, HostResponse, JsonRpcError,
+    JsonRpcMessage, TerminalProfile, WindowType, WorkerRequest,
 };
 use serde::Serialize;
 use serde_json::{json, Value};
@@ -14,11 +19,21 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use tauri::{Emitter, Manager, State, WebviewUrl, WebviewWindow};
-use terminal::TerminalManager;
+use terminal::{
+    complete_utf8_len, OutputEncoding, SearchOptions, SnapshotFormat, SnapshotOptions,
+    TerminalCreateOptions, TerminalManager, TerminalOutputChunk, TerminalSignal,
+};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
 const APP_CHANNEL_FOR_VIEW: &str = "codex_desktop:message-for-view";
 const READ_FILE_ALLOWLIST_ENV: &str = "CODEX_ALLOWED_READ_ROOTS";
+const APP_SERVER_TRACE_ENV: &str = "CODEX_APP_SERVER_TRACE";
+const APP_SERVER_REPLAY_ENV: &str = "CODEX_APP_SERVER_REPLAY";
+const APP_SERVER_URL_ENV: &str = "CODEX_APP_SERVER_URL";
+/// Folders under the app data dir that the view may write exports into.
+const RECORDINGS_DIR: &str = "terminal-recordings";
+const TRACES_DIR: &str = "app-server-traces";
 const LOCAL_ENV_ALLOWLIST: [&str; 6] = ["SHELL", "ComSpec", "HOME", "USERPROFILE", "PATH", "TERM"];
 
 #[derive(Clone)]
@@ -28,6 +43,7 @@ struct RuntimeState {
     store: StateStore,
     terminal: TerminalManager,
     allowed_read_roots: Vec<PathBuf>,
+    data_dir: PathBuf,
     app_server: Option<Arc<AppServerBridge>>,
 }
 
@@ -47,41 +63,35 @@ struct MutationResultEnvelope {
     response: HostResponse,
 }
 
-fn parse_jsonrpc_error(request_id: String, error: &Value) -> HostResponse {
-    let code = error
-        .get("code")
-        .and_then(|value| {
-            if let Some(number) = value.as_i64() {
-                return Some(format!("app_server_{number}"));
-            }
-            value.as_str().map(|item| item.to_string())
-        })
-        .unwrap_or_else(|| "app_server_error".to_string());
-    let message = error
-        .get("message")
-        .and_then(Value::as_str)
-        .unwrap_or("app-server request failed")
-        .to_string();
+fn jsonrpc_error_to_host_response(request_id: String, error: JsonRpcError) -> HostResponse {
     HostResponse {
         request_id,
         ok: false,
         result: None,
         error: Some(host_api::HostError {
-            code,
-            message,
-            details: Some(error.clone()),
+            code: format!("app_server_{}", error.code),
+            details: serde_json::to_value(&error).ok(),
+            message: error.message,
         }),
     }
 }
 
 fn map_app_server_envelope(request_id: String, envelope: Value) -> HostResponse {
-    if let Some(error) = envelope.get("error") {
-        return parse_jsonrpc_error(request_id, error);
+    match JsonRpcMessage::parse(envelope) {
+        Ok(JsonRpcMessage::Response(response)) => HostResponse::ok(request_id, response.result),
+        Ok(JsonRpcMessage::Error(response)) => {
+            jsonrpc_error_to_host_response(request_id, response.error)
+        }
+        Ok(message) => HostResponse::err(
+            request_id,
+            "invalid_app_server_response",
+            format!(
+                "expected a response, got {}",
+                message.method().unwrap_or("a frame without a method")
+            ),
+        ),
+        Err(err) => HostResponse::err(request_id, "invalid_app_server_response", err.to_string()),
     }
-    HostResponse::ok(
-        request_id,
-        envelope.get("result").cloned().unwrap_or_else(|| json!({})),
-    )
 }
 
 fn jsonrpc_error_response(request_id: Value, code: &str, message: impl Into<String>) -> Value {
@@ -128,6 +138,252 @@ fn is_within_allowed_roots(candidate: &Path, allowed_roots: &[PathBuf]) -> bool
     allowed_roots.iter().any(|root| candidate.starts_with(root))
 }
 
+/// Resolves a view-supplied export path to a file directly inside `dir`,
+/// creating `dir` (private to the user) on demand. A bare file name is taken
+/// relative to `dir`; any other path must already point into it.
+fn resolve_export_path(dir: &Path, requested: &str, extension: &str) -> Result<PathBuf, String> {
+    let requested = Path::new(requested);
+    let Some(name) = requested.file_name() else {
+        return Err("path must name a file".to_string());
+    };
+    if requested.extension().is_none_or(|ext| ext != extension) {
+        return Err(format!("path must end in .{extension}"));
+    }
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir).map_err(|err| err.to_string())?;
+    let dir = std::fs::canonicalize(dir).map_err(|err| err.to_string())?;
+    let parent = requested
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    if let Some(parent) = parent {
+        if std::fs::canonicalize(parent).ok().as_deref() != Some(dir.as_path()) {
+            return Err(format!("path must be inside {}", dir.display()));
+        }
+    }
+    Ok(dir.join(name))
+}
+
+/// Host events share the view's message channel; they are told apart from
+/// query and mutation results by their `event` field.
+fn emit_host_event(app: &tauri::AppHandle, event: &str, payload: Value) {
+    let _ = app.emit(
+        APP_CHANNEL_FOR_VIEW,
+        HostEvent {
+            event: event.to_string(),
+            payload,
+        },
+    );
+}
+
+fn string_list_param(params: &Value, key: &str) -> Vec<String> {
+    match params.get(key) {
+        Some(Value::String(value)) => vec![value.clone()],
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(ToString::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Input for `terminal-write`: `base64` (standard alphabet), `bytes` (an array
+/// of numbers) or `text`, checked in that order.
+fn terminal_write_bytes(params: &Value) -> Result<Vec<u8>, String> {
+    if let Some(encoded) = params.get("base64").and_then(Value::as_str) {
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|err| format!("invalid base64 input: {err}"));
+    }
+    if let Some(bytes) = params.get("bytes").and_then(Value::as_array) {
+        return bytes
+            .iter()
+            .map(|byte| {
+                byte.as_u64()
+                    .and_then(|byte| u8::try_from(byte).ok())
+                    .ok_or_else(|| "bytes must be numbers between 0 and 255".to_string())
+            })
+            .collect();
+    }
+    Ok(params
+        .get("text")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .as_bytes()
+        .to_vec())
+}
+
+/// Options for `terminal-create`; explicit params override `defaults`, which
+/// come from the selected profile, and `env` is merged on top of its env.
+fn terminal_create_options(
+    params: &Value,
+    mut defaults: TerminalCreateOptions,
+) -> TerminalCreateOptions {
+    defaults.apply_command(
+        string_list_param(params, "command"),
+        string_list_param(params, "args"),
+    );
+    let mut env = defaults.env;
+    if let Some(overrides) = params.get("env").and_then(Value::as_object) {
+        env.extend(
+            overrides
+                .iter()
+                .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string())),
+        );
+    }
+    TerminalCreateOptions {
+        cwd: params
+            .get("cwd")
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+            .unwrap_or(defaults.cwd),
+        env,
+        cols: params
+            .get("cols")
+            .and_then(Value::as_u64)
+            .map(|value| value as u16)
+            .unwrap_or(defaults.cols),
+        rows: params
+            .get("rows")
+            .and_then(Value::as_u64)
+            .map(|value| value as u16)
+            .unwrap_or(defaults.rows),
+        command: defaults.command,
+        login: params
+            .get("login")
+            .and_then(Value::as_bool)
+            .unwrap_or(defaults.login),
+        interactive: params
+            .get("interactive")
+            .and_then(Value::as_bool)
+            .unwrap_or(defaults.interactive),
+        close_on_exit: params
+            .get("closeOnExit")
+            .and_then(Value::as_bool)
+            .unwrap_or(defaults.close_on_exit),
+        shell_integration: params
+            .get("shellIntegration")
+            .and_then(Value::as_bool)
+            .unwrap_or(defaults.shell_integration),
+        record: params
+            .get("record")
+            .and_then(Value::as_bool)
+            .unwrap_or(defaults.record),
+        profile_id: defaults.profile_id,
+        scrollback_lines: params
+            .get("scrollbackLines")
+            .and_then(Value::as_u64)
+            .map(|value| value as usize)
+            .or(defaults.scrollback_lines),
+        scrollback_bytes: params
+            .get("scrollbackBytes")
+            .and_then(Value::as_u64)
+            .map(|value| value as usize)
+            .or(defaults.scrollback_bytes),
+    }
+}
+
+const TERMINAL_PROFILES_KEY: &str = "terminal-profiles";
+
+async fn load_terminal_profiles(store: &StateStore) -> anyhow::Result<Vec<TerminalProfile>> {
+    let value = store.get_json(TERMINAL_PROFILES_KEY).await?;
+    match value.get("profiles") {
+        Some(profiles) => Ok(serde_json::from_value(profiles.clone())?),
+        None => Ok(Vec::new()),
+    }
+}
+
+async fn save_terminal_profiles(
+    store: &StateStore,
+    profiles: &[TerminalProfile],
+) -> anyhow::Result<()> {
+    store
+        .set_json(TERMINAL_PROFILES_KEY, &json!({ "profiles": profiles }))
+        .await
+}
+
+/// Base options for `terminal-create`: the built-in defaults, or those of the
+/// profile named by `profileId`.
+async fn terminal_create_defaults(
+    store: &StateStore,
+    params: &Value,
+) -> anyhow::Result<TerminalCreateOptions> {
+    let Some(profile_id) = params.get("profileId").and_then(Value::as_str) else {
+        return Ok(TerminalCreateOptions::default());
+    };
+    let profile = load_terminal_profiles(store)
+        .await?
+        .into_iter()
+        .find(|profile| profile.id == profile_id)
+        .ok_or_else(|| anyhow::anyhow!("unknown terminal profile {profile_id}"))?;
+    let cwd = params.get("cwd").and_then(Value::as_str);
+    Ok(TerminalCreateOptions::from_profile(&profile, cwd))
+}
+
+fn spawn_terminal_output_forwarder(
+    app: tauri::AppHandle,
+    mut output: broadcast::Receiver<TerminalOutputChunk>,
+) {
+    tauri::async_runtime::spawn(async move {
+        // Bytes of a character split across PTY reads, held until it completes.
+        let mut pending = Vec::new();
+        let mut next_offset = None;
+        loop {
+            match output.recv().await {
+                Ok(chunk) => {
+                    if next_offset != Some(chunk.offset) {
+                        pending.clear();
+                    }
+                    next_offset = Some(chunk.offset + chunk.data.len() as u64);
+                    let offset = chunk.offset - pending.len() as u64;
+                    pending.extend_from_slice(&chunk.data);
+                    let complete = complete_utf8_len(&pending);
+                    if complete == 0 {
+                        continue;
+                    }
+                    let data = pending.drain(..complete).collect::<Vec<u8>>();
+                    emit_host_event(
+                        &app,
+                        "terminal-output",
+                        json!({
+                            "id": chunk.session_id,
+                            "offset": offset,
+                            "data": String::from_utf8_lossy(&data),
+                            "byteLength": data.len(),
+                        }),
+                    );
+                }
+                // Skipped chunks show up as an offset gap; the view re-attaches to catch up.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+fn spawn_terminal_exit_forwarder(app: tauri::AppHandle, terminal: &TerminalManager) {
+    let mut exits = terminal.subscribe_exits();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match exits.recv().await {
+                Ok(exit) => emit_host_event(
+                    &app,
+                    "terminal-exited",
+                    json!({
+                        "id": exit.session_id,
+                        "exitStatus": exit.status,
+                    }),
+                ),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
 async fn forward_host_request(
     state: &RuntimeState,
     method: &str,
@@ -142,16 +398,19 @@ async fn forward_host_request(
         );
     };
     match bridge
-        .request(
-            method,
-            params,
-            Duration::from_secs(120),
-            Some(Value::String(request_id.clone())),
-        )
+        .call(method, params, Some(Value::String(request_id.clone())))
         .await
     {
         Ok(envelope) => map_app_server_envelope(request_id, envelope),
-        Err(err) => HostResponse::err(request_id, "app_server_error", err.to_string()),
+        Err(err) => HostResponse::err(request_id, app_server_error_code(&err), err.to_string()),
+    }
+}
+
+fn app_server_error_code(err: &anyhow::Error) -> &'static str {
+    if err.is::<RequestCancelled>() {
+        "request_cancelled"
+    } else {
+        "app_server_error"
     }
 }
 
@@ -268,6 +527,136 @@ async fn bridge_handle_query(
                 Err(_) => HostResponse::err(request.request_id, "io_error", "failed to read file"),
             }
         }
+        "terminal-commands" => {
+            let id = request
+                .params
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let limit = request
+                .params
+                .get("limit")
+                .and_then(Value::as_u64)
+                .map(|value| value as usize);
+            let include_output = request
+                .params
+                .get("includeOutput")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            match state.terminal.commands(id, limit, include_output).await {
+                Ok(payload) => HostResponse::ok(request.request_id, payload),
+                Err(err) => {
+                    HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                }
+            }
+        }
+        "terminal-history" => match state.terminal.history().await {
+            Ok(payload) => HostResponse::ok(request.request_id, payload),
+            Err(err) => HostResponse::err(request.request_id, "terminal_error", err.to_string()),
+        },
+        "terminal-history-output" => {
+            let id = request
+                .params
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            match state.terminal.history_output(id).await {
+                Ok(payload) => HostResponse::ok(request.request_id, payload),
+                Err(err) => {
+                    HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                }
+            }
+        }
+        "app-server-diagnostics" => match &state.app_server {
+            Some(bridge) => HostResponse::ok(
+                request.request_id,
+                serde_json::to_value(bridge.diagnostics()).unwrap_or_else(|_| json!({})),
+            ),
+            None => HostResponse::ok(request.request_id, json!({ "state": "unavailable" })),
+        },
+        "app-server-status" => match &state.app_server {
+            Some(bridge) => HostResponse::ok(
+                request.request_id,
+                serde_json::to_value(bridge.status()).unwrap_or_else(|_| json!({})),
+            ),
+            None => HostResponse::ok(request.request_id, json!({ "state": "unavailable" })),
+        },
+        "terminal-list" => match state.terminal.list().await {
+            Ok(payload) => HostResponse::ok(request.request_id, payload),
+            Err(err) => HostResponse::err(request.request_id, "terminal_error", err.to_string()),
+        },
+        "terminal-profiles" => match load_terminal_profiles(&state.store).await {
+            Ok(profiles) => HostResponse::ok(request.request_id, json!({ "profiles": profiles })),
+            Err(err) => HostResponse::err(request.request_id, "state_error", err.to_string()),
+        },
+        "terminal-search" => {
+            let id = request.params.get("id").and_then(Value::as_str);
+            let defaults = SearchOptions::default();
+            let options = SearchOptions {
+                query: request
+                    .params
+                    .get("query")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                regex: request
+                    .params
+                    .get("regex")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(defaults.regex),
+                case_sensitive: request
+                    .params
+                    .get("caseSensitive")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(defaults.case_sensitive),
+                context_lines: request
+                    .params
+                    .get("contextLines")
+                    .and_then(Value::as_u64)
+                    .map(|value| value as usize)
+                    .unwrap_or(defaults.context_lines),
+                max_results: request
+                    .params
+                    .get("maxResults")
+                    .and_then(Value::as_u64)
+                    .map(|value| value as usize)
+                    .unwrap_or(defaults.max_results),
+            };
+            match state.terminal.search(id, options).await {
+                Ok(payload) => HostResponse::ok(request.request_id, payload),
+                Err(err) => {
+                    HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                }
+            }
+        }
+        "terminal-stats" => match state.terminal.stats().await {
+            Ok(payload) => HostResponse::ok(request.request_id, payload),
+            Err(err) => HostResponse::err(request.request_id, "terminal_error", err.to_string()),
+        },
+        "terminal-snapshot" => {
+            let id = request
+                .params
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let options = SnapshotOptions {
+                format: match request.params.get("format").and_then(Value::as_str) {
+                    Some("styled") => SnapshotFormat::Styled,
+                    _ => SnapshotFormat::Text,
+                },
+                include_scrollback: request
+                    .params
+                    .get("includeScrollback")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+            };
+            match state.terminal.snapshot(id, options).await {
+                Ok(payload) => HostResponse::ok(request.request_id, payload),
+                Err(err) => {
+                    HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                }
+            }
+        }
         _ if is_known_query_method(&request.method) => {
             forward_host_request(&state, &request.method, request.params, request.request_id).await
         }
@@ -282,10 +671,75 @@ async fn bridge_handle_query(
 
 #[tauri::command]
 async fn bridge_handle_mutation(
+    app: tauri::AppHandle,
     state: State<'_, RuntimeState>,
     request: HostMutationRequest,
 ) -> Result<MutationResultEnvelope, String> {
     let response = match request.method.as_str() {
+        "app-server-cancel-request" => {
+            let target = request
+                .params
+                .get("requestId")
+                .cloned()
+                .unwrap_or(Value::Null);
+            match &state.app_server {
+                _ if target.is_null() => HostResponse::err(
+                    request.request_id,
+                    "invalid_request_id",
+                    "requestId is required",
+                ),
+                Some(bridge) => match bridge.cancel(&target).await {
+                    Ok(cancelled) => {
+                        HostResponse::ok(request.request_id, json!({ "cancelled": cancelled }))
+                    }
+                    Err(err) => {
+                        HostResponse::err(request.request_id, "app_server_error", err.to_string())
+                    }
+                },
+                None => HostResponse::err(
+                    request.request_id,
+                    "app_server_unavailable",
+                    "app-server bridge is unavailable",
+                ),
+            }
+        }
+        "app-server-trace-start" => {
+            let path = request
+                .params
+                .get("path")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            // Traces hold auth payloads, so they only go to the private traces folder.
+            let path = resolve_export_path(&state.data_dir.join(TRACES_DIR), path, "jsonl");
+            match (&state.app_server, path) {
+                (_, Err(err)) => HostResponse::err(request.request_id, "invalid_path", err),
+                (Some(bridge), Ok(path)) => match bridge.start_trace(&path) {
+                    Ok(()) => HostResponse::ok(
+                        request.request_id,
+                        json!({ "path": path.to_string_lossy().to_string() }),
+                    ),
+                    Err(err) => {
+                        HostResponse::err(request.request_id, "trace_error", format!("{err:#}"))
+                    }
+                },
+                (None, Ok(_)) => HostResponse::err(
+                    request.request_id,
+                    "app_server_unavailable",
+                    "app-server bridge is unavailable",
+                ),
+            }
+        }
+        "app-server-trace-stop" => match &state.app_server {
+            Some(bridge) => HostResponse::ok(
+                request.request_id,
+                json!({ "path": bridge.stop_trace().map(|path| path.display().to_string()) }),
+            ),
+            None => HostResponse::err(
+                request.request_id,
+                "app_server_unavailable",
+                "app-server bridge is unavailable",
+            ),
+        },
         "set-configuration" => {
             let value = request
                 .params
@@ -308,13 +762,77 @@ async fn bridge_handle_mutation(
                 Err(err) => HostResponse::err(request.request_id, "state_error", err.to_string()),
             }
         }
-        "terminal-create" => {
-            let cwd = request
+        "terminal-create" => match terminal_create_defaults(&state.store, &request.params).await {
+            Ok(defaults) => {
+                let options = terminal_create_options(&request.params, defaults);
+                match state.terminal.create(options).await {
+                    Ok(session) => {
+                        if let Ok(output) = state.terminal.subscribe(&session.id).await {
+                            spawn_terminal_output_forwarder(app.clone(), output);
+                        }
+                        HostResponse::ok(
+                            request.request_id,
+                            serde_json::to_value(session).unwrap_or_else(|_| json!({})),
+                        )
+                    }
+                    Err(err) => {
+                        HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                    }
+                }
+            }
+            Err(err) => HostResponse::err(
+                request.request_id,
+                "terminal_profile_error",
+                err.to_string(),
+            ),
+        },
+        "terminal-attach" => {
+            let id = request
                 .params
-                .get("cwd")
+                .get("id")
                 .and_then(Value::as_str)
-                .unwrap_or(".")
-                .to_string();
+                .unwrap_or_default();
+            let since = request.params.get("since").and_then(Value::as_u64);
+            let encoding = match request.params.get("encoding").and_then(Value::as_str) {
+                Some(name) => OutputEncoding::parse(name),
+                None => Some(OutputEncoding::Text),
+            };
+            match encoding {
+                Some(encoding) => match state.terminal.attach(id, since, encoding).await {
+                    Ok(payload) => HostResponse::ok(request.request_id, payload),
+                    Err(err) => {
+                        HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                    }
+                },
+                None => HostResponse::err(
+                    request.request_id,
+                    "invalid_encoding",
+                    "encoding must be one of text, base64 or bytes",
+                ),
+            }
+        }
+        "terminal-write" => {
+            let id = request
+                .params
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            match terminal_write_bytes(&request.params) {
+                Ok(bytes) => match state.terminal.write_bytes(id, &bytes).await {
+                    Ok(()) => HostResponse::ok(request.request_id, json!({ "ok": true })),
+                    Err(err) => {
+                        HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                    }
+                },
+                Err(message) => HostResponse::err(request.request_id, "invalid_input", message),
+            }
+        }
+        "terminal-resize" => {
+            let id = request
+                .params
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
             let cols = request
                 .params
                 .get("cols")
@@ -325,93 +843,238 @@ async fn bridge_handle_mutation(
                 .get("rows")
                 .and_then(Value::as_u64)
                 .unwrap_or(30) as u16;
-            let env = request
-                .params
-                .get("env")
-                .and_then(Value::as_object)
-                .map(|obj| {
-                    obj.iter()
-                        .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
-                        .collect::<HashMap<String, String>>()
-                })
-                .unwrap_or_default();
-            match state.terminal.create(cwd, env, cols, rows).await {
-                Ok(session) => HostResponse::ok(
-                    request.request_id,
-                    serde_json::to_value(session).unwrap_or_else(|_| json!({})),
-                ),
+            match state.terminal.resize(id, cols, rows).await {
+                Ok(()) => HostResponse::ok(request.request_id, json!({ "ok": true })),
                 Err(err) => {
                     HostResponse::err(request.request_id, "terminal_error", err.to_string())
                 }
             }
         }
-        "terminal-attach" => {
+        "terminal-close" => {
             let id = request
                 .params
                 .get("id")
                 .and_then(Value::as_str)
                 .unwrap_or_default();
-            match state.terminal.attach(id).await {
-                Ok(payload) => HostResponse::ok(request.request_id, payload),
+            let graceful = request
+                .params
+                .get("graceful")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let result = if graceful {
+                let timeout_ms = request
+                    .params
+                    .get("timeoutMs")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(3_000);
+                state
+                    .terminal
+                    .close_gracefully(id, Duration::from_millis(timeout_ms))
+                    .await
+            } else {
+                state.terminal.close(id).await
+            };
+            match result {
+                Ok(()) => HostResponse::ok(request.request_id, json!({ "ok": true })),
                 Err(err) => {
                     HostResponse::err(request.request_id, "terminal_error", err.to_string())
                 }
             }
         }
-        "terminal-write" => {
+        "terminal-restart" => {
             let id = request
                 .params
                 .get("id")
                 .and_then(Value::as_str)
                 .unwrap_or_default();
-            let text = request
+            match state.terminal.restart(id).await {
+                Ok(session) => {
+                    if let Ok(output) = state.terminal.subscribe(&session.id).await {
+                        spawn_terminal_output_forwarder(app.clone(), output);
+                    }
+                    HostResponse::ok(
+                        request.request_id,
+                        serde_json::to_value(session).unwrap_or_else(|_| json!({})),
+                    )
+                }
+                Err(err) => {
+                    HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                }
+            }
+        }
+        "terminal-history-delete" => {
+            let id = request
                 .params
-                .get("text")
+                .get("id")
                 .and_then(Value::as_str)
                 .unwrap_or_default();
-            match state.terminal.write(id, text).await {
+            match state.terminal.forget_history(id).await {
                 Ok(()) => HostResponse::ok(request.request_id, json!({ "ok": true })),
                 Err(err) => {
                     HostResponse::err(request.request_id, "terminal_error", err.to_string())
                 }
             }
         }
-        "terminal-resize" => {
+        "terminal-recording-start" | "terminal-recording-stop" => {
             let id = request
                 .params
                 .get("id")
                 .and_then(Value::as_str)
                 .unwrap_or_default();
-            let cols = request
-                .params
-                .get("cols")
-                .and_then(Value::as_u64)
-                .unwrap_or(120) as u16;
-            let rows = request
+            let result = if request.method == "terminal-recording-start" {
+                state.terminal.start_recording(id).await
+            } else {
+                state.terminal.stop_recording(id).await
+            };
+            match result {
+                Ok(status) => HostResponse::ok(request.request_id, status),
+                Err(err) => {
+                    HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                }
+            }
+        }
+        "terminal-recording-export" => {
+            let id = request
                 .params
-                .get("rows")
-                .and_then(Value::as_u64)
-                .unwrap_or(30) as u16;
-            match state.terminal.resize(id, cols, rows).await {
-                Ok(()) => HostResponse::ok(request.request_id, json!({ "ok": true })),
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let path = request.params.get("path").and_then(Value::as_str);
+            let path = path.map(|path| {
+                resolve_export_path(&state.data_dir.join(RECORDINGS_DIR), path, "cast")
+            });
+            match state.terminal.export_recording(id).await {
+                Ok(cast) => match path {
+                    Some(Err(err)) => HostResponse::err(request.request_id, "invalid_path", err),
+                    Some(Ok(path)) => match tokio::fs::write(&path, cast).await {
+                        Ok(()) => HostResponse::ok(
+                            request.request_id,
+                            json!({ "path": path.to_string_lossy().to_string() }),
+                        ),
+                        Err(err) => {
+                            HostResponse::err(request.request_id, "io_error", err.to_string())
+                        }
+                    },
+                    None => HostResponse::ok(request.request_id, json!({ "cast": cast })),
+                },
                 Err(err) => {
                     HostResponse::err(request.request_id, "terminal_error", err.to_string())
                 }
             }
         }
-        "terminal-close" => {
+        "terminal-set-label" => {
             let id = request
                 .params
                 .get("id")
                 .and_then(Value::as_str)
                 .unwrap_or_default();
-            match state.terminal.close(id).await {
-                Ok(()) => HostResponse::ok(request.request_id, json!({ "ok": true })),
+            let label = request
+                .params
+                .get("label")
+                .and_then(Value::as_str)
+                .map(ToString::to_string);
+            match state.terminal.set_label(id, label).await {
+                Ok(session) => HostResponse::ok(
+                    request.request_id,
+                    serde_json::to_value(session).unwrap_or_else(|_| json!({})),
+                ),
                 Err(err) => {
                     HostResponse::err(request.request_id, "terminal_error", err.to_string())
                 }
             }
         }
+        "terminal-profile-save" => {
+            let profile = request
+                .params
+                .get("profile")
+                .cloned()
+                .map(serde_json::from_value::<TerminalProfile>);
+            match profile {
+                Some(Ok(mut profile)) => {
+                    if profile.id.trim().is_empty() {
+                        profile.id = Uuid::new_v4().to_string();
+                    }
+                    let result = async {
+                        let mut profiles = load_terminal_profiles(&state.store).await?;
+                        match profiles
+                            .iter_mut()
+                            .find(|existing| existing.id == profile.id)
+                        {
+                            Some(existing) => *existing = profile.clone(),
+                            None => profiles.push(profile.clone()),
+                        }
+                        save_terminal_profiles(&state.store, &profiles).await
+                    }
+                    .await;
+                    match result {
+                        Ok(()) => HostResponse::ok(
+                            request.request_id,
+                            serde_json::to_value(profile).unwrap_or_else(|_| json!({})),
+                        ),
+                        Err(err) => {
+                            HostResponse::err(request.request_id, "state_error", err.to_string())
+                        }
+                    }
+                }
+                Some(Err(err)) => {
+                    HostResponse::err(request.request_id, "invalid_profile", err.to_string())
+                }
+                None => {
+                    HostResponse::err(request.request_id, "invalid_profile", "profile is required")
+                }
+            }
+        }
+        "terminal-profile-delete" => {
+            let id = request
+                .params
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let result = async {
+                let mut profiles = load_terminal_profiles(&state.store).await?;
+                let before = profiles.len();
+                profiles.retain(|profile| profile.id != id);
+                if profiles.len() != before {
+                    save_terminal_profiles(&state.store, &profiles).await?;
+                }
+                Ok::<bool, anyhow::Error>(profiles.len() != before)
+            }
+            .await;
+            match result {
+                Ok(removed) => HostResponse::ok(request.request_id, json!({ "removed": removed })),
+                Err(err) => HostResponse::err(request.request_id, "state_error", err.to_string()),
+            }
+        }
+        "terminal-signal" => {
+            let id = request
+                .params
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let signal = request
+                .params
+                .get("signal")
+                .and_then(Value::as_str)
+                .and_then(TerminalSignal::parse);
+            let tree = request
+                .params
+                .get("tree")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            match signal {
+                Some(signal) => match state.terminal.signal(id, signal, tree).await {
+                    Ok(()) => HostResponse::ok(request.request_id, json!({ "ok": true })),
+                    Err(err) => {
+                        HostResponse::err(request.request_id, "terminal_error", err.to_string())
+                    }
+                },
+                None => HostResponse::err(
+                    request.request_id,
+                    "invalid_signal",
+                    "signal must be one of SIGINT, SIGTERM, SIGHUP or SIGKILL",
+                ),
+            }
+        }
         _ if is_known_mutation_method(&request.method) => {
             forward_host_request(&state, &request.method, request.params, request.request_id).await
         }
@@ -461,6 +1124,21 @@ async fn bridge_send_message_from_view(
         .or_else(|| payload.get("params").cloned())
         .unwrap_or_else(|| json!({}));
 
+    // A reply to a request the app-server sent to the view.
+    if method.is_none() && (request.get("result").is_some() || request.get("error").is_some()) {
+        let Some(bridge) = &state.app_server else {
+            return Err("app-server bridge is unavailable".to_string());
+        };
+        let reply = match request.get("error") {
+            Some(error) => Err(error.clone()),
+            None => Ok(request.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        return bridge
+            .respond(request_id, reply)
+            .await
+            .map_err(|err| err.to_string());
+    }
+
     if let Some(method) = method {
         if !is_known_query_method(&method) && !is_known_mutation_method(&method) {
             let response = jsonrpc_error_response(
@@ -473,17 +1151,11 @@ async fn bridge_send_message_from_view(
                 .map_err(|err| err.to_string());
         }
         let response = if let Some(bridge) = &state.app_server {
-            match bridge
-                .request(
-                    &method,
-                    params,
-                    Duration::from_secs(120),
-                    Some(request_id.clone()),
-                )
-                .await
-            {
+            match bridge.call(&method, params, Some(request_id.clone())).await {
                 Ok(value) => value,
-                Err(err) => jsonrpc_error_response(request_id, "app_server_error", err.to_string()),
+                Err(err) => {
+                    jsonrpc_error_response(request_id, app_server_error_code(&err), err.to_string())
+                }
             }
         } else {
             jsonrpc_error_response(
@@ -710,17 +1382,33 @@ fn resolve_codex_cli_path() -> Option<PathBuf> {
 }
 
 async fn maybe_start_app_server_bridge() -> Option<Arc<AppServerBridge>> {
-    let Some(cli_path) = resolve_codex_cli_path() else {
-        eprintln!("[tauri-rewrite] app-server disabled: failed to resolve codex cli path");
-        return None;
+    let started = if let Some(trace) = std::env::var_os(APP_SERVER_REPLAY_ENV) {
+        AppServerBridge::replay(Path::new(&trace)).await
+    } else if let Ok(url) = std::env::var(APP_SERVER_URL_ENV) {
+        match AppServerTransport::parse_url(&url) {
+            Ok(transport) => AppServerBridge::connect(transport).await,
+            Err(err) => Err(err),
+        }
+    } else {
+        let Some(cli_path) = resolve_codex_cli_path() else {
+            eprintln!("[tauri-rewrite] app-server disabled: failed to resolve codex cli path");
+            return None;
+        };
+        AppServerBridge::spawn(Path::new(&cli_path), &[]).await
     };
-    match AppServerBridge::spawn(Path::new(&cli_path), &[]).await {
-        Ok(bridge) => Some(Arc::new(bridge)),
+    let bridge = match started {
+        Ok(bridge) => bridge,
         Err(err) => {
             eprintln!("[tauri-rewrite] app-server disabled: {err}");
-            None
+            return None;
+        }
+    };
+    if let Some(trace) = std::env::var_os(APP_SERVER_TRACE_ENV) {
+        if let Err(err) = bridge.start_trace(Path::new(&trace)) {
+            eprintln!("[tauri-rewrite] app-server trace disabled: {err}");
         }
     }
+    Some(Arc::new(bridge))
 }
 
 #[tokio::main]
@@ -733,7 +1421,43 @@ async fn main() -> anyhow::Result<()> {
     let data_dir = dirs::data_local_dir()
         .unwrap_or_else(|| PathBuf::from("."))
         .join("codex-tauri-rewrite");
-    let store = StateStore::new(data_dir).await?;
+    let terminal = TerminalManager::with_history(data_dir.join("terminal-sessions"))?;
+    let store = StateStore::new(&data_dir).await?;
+    let terminal_settings = store
+        .get_json("terminal-settings")
+        .await
+        .unwrap_or_default();
+    if let Some(budget) = terminal_settings
+        .get("memoryBudgetBytes")
+        .and_then(Value::as_u64)
+    {
+        terminal.set_memory_budget(budget as usize);
+    }
+
+    if let Some(bridge) = &app_server {
+        let mut policy = TimeoutPolicy::default();
+        let overrides = store
+            .get_json("app-server-timeouts")
+            .await
+            .unwrap_or_default();
+        if let Err(err) = policy.apply_overrides(&overrides) {
+            eprintln!("[tauri-rewrite] ignoring invalid app-server-timeouts: {err}");
+        }
+        bridge.set_timeout_policy(policy);
+
+        let shutdown = store
+            .get_json("app-server-shutdown")
+            .await
+            .unwrap_or_default();
+        if !shutdown.is_null() {
+            match serde_json::from_value::<ShutdownPolicy>(shutdown) {
+                Ok(policy) => bridge.set_shutdown_policy(policy),
+                Err(err) => {
+                    eprintln!("[tauri-rewrite] ignoring invalid app-server-shutdown: {err}");
+                }
+            }
+        }
+    }
 
     let runtime_state = RuntimeState {
         build_flavor,
@@ -741,10 +1465,12 @@ async fn main() -> anyhow::Result<()> {
             codex_app_session_id: session_id,
         },
         store,
-        terminal: TerminalManager::default(),
+        terminal,
         allowed_read_roots,
+        data_dir,
         app_server,
     };
+    let exiting_app_server = runtime_state.app_server.clone();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
@@ -771,18 +1497,93 @@ async fn main() -> anyhow::Result<()> {
                 runtime_state.sentry.codex_app_session_id.clone(),
             )?;
 
+            spawn_terminal_exit_forwarder(app.handle().clone(), &runtime_state.terminal);
+
             if let Some(bridge) = runtime_state.app_server.clone() {
                 let app_handle = app.handle().clone();
+                let mut status = bridge.subscribe_status();
+                tauri::async_runtime::spawn(async move {
+                    loop {
+                        match status.recv().await {
+                            Ok(update) => emit_host_event(
+                                &app_handle,
+                                "app-server-status",
+                                serde_json::to_value(update).unwrap_or_else(|_| json!({})),
+                            ),
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        }
+                    }
+                });
+                let app_handle = app.handle().clone();
+                let mut server_requests = bridge.subscribe_server_requests();
+                let requests_bridge = Arc::clone(&bridge);
                 tauri::async_runtime::spawn(async move {
-                    let mut notifications = bridge.subscribe_notifications();
-                    while let Ok(notification) = notifications.recv().await {
-                        let _ = app_handle.emit(APP_CHANNEL_FOR_VIEW, notification);
+                    loop {
+                        let requests = match server_requests.recv().await {
+                            Ok(request) => vec![request],
+                            // Re-send whatever is still unanswered rather than losing prompts.
+                            Err(broadcast::error::RecvError::Lagged(_)) => {
+                                requests_bridge.outstanding_server_requests().await
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        };
+                        for request in requests {
+                            let _ = app_handle.emit(
+                                APP_CHANNEL_FOR_VIEW,
+                                json!({
+                                    "jsonrpc": "2.0",
+                                    "id": request.id,
+                                    "method": request.method,
+                                    "params": request.params,
+                                }),
+                            );
+                        }
+                    }
+                });
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    let mut notifications =
+                        bridge.notification_subscription(NotificationFilter::all());
+                    while let Some(event) = notifications.recv().await {
+                        match event {
+                            NotificationEvent::Notification(notification) => {
+                                let _ = app_handle.emit(APP_CHANNEL_FOR_VIEW, notification);
+                            }
+                            NotificationEvent::Lagged { dropped } => {
+                                eprintln!(
+                                    "[tauri-rewrite] dropped {dropped} app-server notification(s)"
+                                );
+                                emit_host_event(
+                                    &app_handle,
+                                    "app-server-notifications-lagged",
+                                    json!({
+                                        "dropped": dropped,
+                                        "totalDropped": notifications.dropped(),
+                                    }),
+                                );
+                            }
+                        }
                     }
                 });
             }
 
             Ok(())
         })
-        .run(tauri::generate_context!())?;
+        .build(tauri::generate_context!())?
+        .run(move |_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // The process exits right after this event, so the app-server
+                // has to be stopped before returning or it is left running.
+                if let Some(bridge) = &exiting_app_server {
+                    let stopped = tokio::task::block_in_place(|| {
+                        tokio::runtime::Handle::current().block_on(bridge.shutdown())
+                    });
+                    if let Err(err) = stopped {
+                        eprintln!("[tauri-rewrite] app-server shutdown failed: {err:#}");
+                    }
+                }
+            }
+        });
     Ok(())
 }
@@ -0,0 +1,179 @@
+//! Scriptable stand-in for `codex app-server`, used by the bridge's
+//! integration tests. It speaks newline-delimited JSON-RPC on stdin/stdout and
+//! answers every request with `{"method": .., "params": ..}` unless the
+//! request's params carry a `mock` object:
+//!
+//! - `delayMs`: wait before answering; other requests are served meanwhile.
+//! - `notifications`: `[{method, params}]` sent before the answer, each after
+//!   `notificationIntervalMs`.
+//! - `stderr`: lines written to stderr.
+//! - `error`: answer with this error object instead of a result.
+//! - `noResponse`: never answer.
+//! - `rawFrame`: write this line verbatim instead of answering.
+//! - `crash`: exit with this code instead of answering.
+//! - `answerAfterExitMs`: exit at once, leaving a shell that inherited stdout
+//!   to write the answer after this delay (Unix only).
+//! - `serverRequest`: `{method, params}` sent to the client first; the answer
+//!   is `{"reply": <the client's response>}`.
+//! - `ignoreEof`: keep running after stdin closes, until killed.
+//!
+//! The `mock/received` request returns every message received so far. When
+//! stdin closes, requests still being answered are finished before exiting.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type Output = Arc<Mutex<std::io::Stdout>>;
+
+fn main() {
+    let out: Output = Arc::new(Mutex::new(std::io::stdout()));
+    let mut received = Vec::new();
+    // Server request id -> id of the client request waiting on its reply.
+    let waiting: Arc<Mutex<HashMap<String, Value>>> = Arc::new(Mutex::new(HashMap::new()));
+    let mut next_server_id = 0u64;
+    let mut responders = Vec::new();
+    let mut ignore_eof = false;
+
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            eprintln!("mock-app-server: ignoring invalid json: {line}");
+            continue;
+        };
+        received.push(message.clone());
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A client response to one of our server requests.
+            let waiter = id
+                .as_ref()
+                .and_then(|id| waiting.lock().unwrap().remove(&id.to_string()));
+            if let Some(waiter) = waiter {
+                let reply = match message.get("error") {
+                    Some(error) => json!({ "error": error }),
+                    None => json!({ "result": message.get("result") }),
+                };
+                send(&out, json!({ "id": waiter, "result": { "reply": reply } }));
+            }
+            continue;
+        };
+        let Some(id) = id else {
+            continue;
+        };
+        if method == "mock/received" {
+            send(
+                &out,
+                json!({ "id": id, "result": { "messages": received } }),
+            );
+            continue;
+        }
+
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let mock = params.get("mock").cloned().unwrap_or(Value::Null);
+        ignore_eof |= mock.get("ignoreEof").and_then(Value::as_bool) == Some(true);
+        let server_request = mock.get("serverRequest").cloned().map(|request| {
+            next_server_id += 1;
+            let server_id = json!(format!("mock-{next_server_id}"));
+            waiting
+                .lock()
+                .unwrap()
+                .insert(server_id.to_string(), id.clone());
+            json!({
+                "id": server_id,
+                "method": request.get("method"),
+                "params": request.get("params"),
+            })
+        });
+        let out = Arc::clone(&out);
+        let method = method.to_string();
+        responders.push(thread::spawn(move || {
+            respond(&out, id, &method, params, &mock, server_request)
+        }));
+    }
+
+    if ignore_eof {
+        loop {
+            thread::park();
+        }
+    }
+    for responder in responders {
+        let _ = responder.join();
+    }
+}
+
+fn respond(
+    out: &Output,
+    id: Value,
+    method: &str,
+    params: Value,
+    mock: &Value,
+    server_request: Option<Value>,
+) {
+    let millis =
+        |key: &str| Duration::from_millis(mock.get(key).and_then(Value::as_u64).unwrap_or(0));
+    for line in mock
+        .get("stderr")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        eprintln!("{}", line.as_str().unwrap_or_default());
+    }
+    for notification in mock
+        .get("notifications")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        thread::sleep(millis("notificationIntervalMs"));
+        send(out, notification.clone());
+    }
+    thread::sleep(millis("delayMs"));
+    if let Some(delay) = mock.get("answerAfterExitMs").and_then(Value::as_u64) {
+        let frame = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": { "method": method, "params": params },
+        });
+        let script = format!("sleep {}; printf '%s\\n' \"$1\"", delay as f64 / 1000.0);
+        let _ = std::process::Command::new("sh")
+            .args(["-c", &script, "sh", &frame.to_string()])
+            .spawn();
+        std::process::exit(0);
+    }
+    if let Some(code) = mock.get("crash").and_then(Value::as_i64) {
+        std::process::exit(code as i32);
+    }
+    if let Some(request) = server_request {
+        send(out, request);
+        return;
+    }
+    if mock.get("noResponse").and_then(Value::as_bool) == Some(true) {
+        return;
+    }
+    if let Some(frame) = mock.get("rawFrame").and_then(Value::as_str) {
+        let mut out = out.lock().unwrap();
+        let _ = writeln!(out, "{frame}");
+        let _ = out.flush();
+        return;
+    }
+    match mock.get("error") {
+        Some(error) => send(out, json!({ "id": id, "error": error })),
+        None => send(
+            out,
+            json!({ "id": id, "result": { "method": method, "params": params } }),
+        ),
+    }
+}
+
+fn send(out: &Output, mut message: Value) {
+    message["jsonrpc"] = json!("2.0");
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{message}");
+    let _ = out.flush();
+}
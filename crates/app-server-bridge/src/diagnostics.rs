@@ -0,0 +1,232 @@
+use crate::{unix_time_ms, AppServerStatus};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::process::ExitStatus;
+
+/// Stderr lines kept per bridge.
+const STDERR_CAPACITY: usize = 1000;
+/// Longer lines are cut at a character boundary below this many bytes.
+const STDERR_LINE_MAX_BYTES: usize = 8 * 1024;
+/// Stderr lines copied into a crash report.
+const CRASH_STDERR_TAIL: usize = 100;
+const CRASH_REPORT_CAPACITY: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    /// The line is not a structured log record.
+    Unknown,
+}
+
+impl LogLevel {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw.to_ascii_uppercase().as_str() {
+            "TRACE" => Some(Self::Trace),
+            "DEBUG" => Some(Self::Debug),
+            "INFO" => Some(Self::Info),
+            "WARN" | "WARNING" => Some(Self::Warn),
+            "ERROR" => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StderrLine {
+    pub timestamp_ms: u64,
+    pub level: LogLevel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub message: String,
+}
+
+impl StderrLine {
+    /// Parses `tracing` output, either JSON records or the default text
+    /// format (`<timestamp> LEVEL target: message`, possibly colored).
+    pub(crate) fn parse(line: &str) -> Self {
+        let line = truncate(&strip_ansi(line), STDERR_LINE_MAX_BYTES);
+        let (level, target, message) = parse_json_record(&line)
+            .or_else(|| parse_text_record(&line))
+            .unwrap_or((LogLevel::Unknown, None, line));
+        Self {
+            timestamp_ms: unix_time_ms(),
+            level,
+            target,
+            message,
+        }
+    }
+}
+
+fn parse_json_record(line: &str) -> Option<(LogLevel, Option<String>, String)> {
+    let record: Value = serde_json::from_str(line).ok()?;
+    let level = LogLevel::parse(record.get("level")?.as_str()?)?;
+    let target = record
+        .get("target")
+        .and_then(Value::as_str)
+        .map(ToString::to_string);
+    let message = record
+        .pointer("/fields/message")
+        .or_else(|| record.get("message"))
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+        .unwrap_or_else(|| line.to_string());
+    Some((level, target, message))
+}
+
+fn parse_text_record(line: &str) -> Option<(LogLevel, Option<String>, String)> {
+    // The level is the first or second word, after an optional timestamp.
+    let mut rest = line.trim_start();
+    let mut level = None;
+    for _ in 0..2 {
+        let (word, after) = rest.split_once(char::is_whitespace)?;
+        rest = after.trim_start();
+        level = LogLevel::parse(word);
+        if level.is_some() {
+            break;
+        }
+    }
+    let level = level?;
+    let (target, message) = match rest.split_once(": ") {
+        Some((target, message)) if !target.is_empty() && !target.contains(char::is_whitespace) => {
+            (Some(target.to_string()), message.to_string())
+        }
+        _ => (None, rest.to_string()),
+    };
+    Some((level, target, message))
+}
+
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\u{1b}' {
+            out.push(ch);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            for next in chars.by_ref() {
+                if ('@'..='~').contains(&next) {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
+fn truncate(line: &str, max_bytes: usize) -> String {
+    if line.len() <= max_bytes {
+        return line.to_string();
+    }
+    let mut end = max_bytes;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &line[..end])
+}
+
+/// What was known about the app-server when it went away.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub timestamp_ms: u64,
+    pub transport: String,
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    pub uptime_ms: u64,
+    /// Restarts before this crash.
+    pub restarts: u32,
+    pub stderr_tail: Vec<StderrLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppServerDiagnostics {
+    pub status: AppServerStatus,
+    pub transport: String,
+    pub stderr: Vec<StderrLine>,
+    /// Stderr lines evicted from the buffer so far.
+    pub stderr_dropped: u64,
+    /// Most recent last.
+    pub crashes: Vec<CrashReport>,
+}
+
+#[derive(Default)]
+pub(crate) struct DiagnosticsLog {
+    stderr: VecDeque<StderrLine>,
+    stderr_dropped: u64,
+    crashes: VecDeque<CrashReport>,
+}
+
+impl DiagnosticsLog {
+    pub(crate) fn push_stderr(&mut self, line: StderrLine) {
+        if self.stderr.len() == STDERR_CAPACITY {
+            self.stderr.pop_front();
+            self.stderr_dropped += 1;
+        }
+        self.stderr.push_back(line);
+    }
+
+    pub(crate) fn record_crash(
+        &mut self,
+        status: &AppServerStatus,
+        transport: String,
+        reason: String,
+        exit: Option<ExitStatus>,
+        uptime_ms: u64,
+    ) -> CrashReport {
+        let skip = self.stderr.len().saturating_sub(CRASH_STDERR_TAIL);
+        let report = CrashReport {
+            timestamp_ms: unix_time_ms(),
+            transport,
+            reason,
+            pid: status.pid,
+            exit_code: exit.and_then(|exit| exit.code()),
+            signal: exit.and_then(exit_signal),
+            uptime_ms,
+            restarts: status.restarts,
+            stderr_tail: self.stderr.iter().skip(skip).cloned().collect(),
+        };
+        if self.crashes.len() == CRASH_REPORT_CAPACITY {
+            self.crashes.pop_front();
+        }
+        self.crashes.push_back(report.clone());
+        report
+    }
+
+    pub(crate) fn snapshot(
+        &self,
+        status: AppServerStatus,
+        transport: String,
+    ) -> AppServerDiagnostics {
+        AppServerDiagnostics {
+            status,
+            transport,
+            stderr: self.stderr.iter().cloned().collect(),
+            stderr_dropped: self.stderr_dropped,
+            crashes: self.crashes.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn exit_signal(exit: ExitStatus) -> Option<i32> {
+    std::os::unix::process::ExitStatusExt::signal(&exit)
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_exit: ExitStatus) -> Option<i32> {
+    None
+}
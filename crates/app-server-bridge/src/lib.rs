@@ -1,146 +1,577 @@
+mod diagnostics;
+mod notifications;
+mod shutdown;
+mod timeouts;
+mod trace;
+mod transport;
+
+pub use diagnostics::{AppServerDiagnostics, CrashReport, LogLevel, StderrLine};
+pub use notifications::{NotificationEvent, NotificationFilter, NotificationSubscription};
+pub use shutdown::ShutdownPolicy;
+pub use timeouts::{MethodTimeout, TimeoutPolicy};
+pub use trace::{read_trace, TraceDirection, TraceRecord};
+pub use transport::AppServerTransport;
+
 use anyhow::{anyhow, Context, Result};
+use diagnostics::DiagnosticsLog;
+use host_api::{JsonRpcError, JsonRpcFrameError, JsonRpcMessage, JsonRpcRequest, RequestId};
+use serde::Serialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, ChildStdin, Command};
-use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::process::Child;
+use tokio::sync::{broadcast, oneshot, Mutex, Notify};
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
+use trace::TraceWriter;
 use tracing::{debug, error, info, warn};
+use transport::{Connection, Reader, Writer};
 
-pub struct AppServerBridge {
-    stdin: Arc<Mutex<ChildStdin>>,
-    pending: Arc<Mutex<HashMap<String, oneshot::Sender<Value>>>>,
+const RESTART_BACKOFF_INITIAL: Duration = Duration::from_millis(500);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// A process that stayed up at least this long restarts without backoff.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the stdout reader may keep delivering what an exited process wrote.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+const NOTIFICATION_CHANNEL_CAPACITY: usize = 1024;
+const STATUS_CHANNEL_CAPACITY: usize = 64;
+const SERVER_REQUEST_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AppServerState {
+    Starting,
+    Ready,
+    Crashed,
+    Restarting,
+    /// Shutting down; see [`ShutdownPolicy`].
+    Stopping,
+    Stopped,
+}
+
+/// Lifecycle of the supervised app-server process, published on every
+/// transition.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppServerStatus {
+    pub state: AppServerState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Restarts since the bridge was spawned.
+    pub restarts: u32,
+    /// Consecutive restart attempt while `restarting`, 0 otherwise.
+    pub attempt: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+type PendingMap = HashMap<RequestId, oneshot::Sender<Result<Value, PendingFailure>>>;
+
+/// Why a pending request was resolved without a response.
+enum PendingFailure {
+    Failed(String),
+    Cancelled,
+}
+
+/// Returned by [`AppServerBridge::request`] when the call was cancelled
+/// through [`AppServerBridge::cancel`].
+#[derive(Debug, thiserror::Error)]
+#[error("app-server request id={id} method={method} was cancelled")]
+pub struct RequestCancelled {
+    pub id: String,
+    pub method: String,
+}
+
+/// A request sent by the app-server to the client, e.g. an approval prompt.
+/// It stays outstanding until answered through [`AppServerBridge::respond`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerRequest {
+    pub id: RequestId,
+    pub method: String,
+    pub params: Value,
+}
+
+/// Answers server requests synchronously. Returning `None` passes the request
+/// on to [`AppServerBridge::subscribe_server_requests`] subscribers.
+pub type ServerRequestHandler =
+    Box<dyn Fn(&ServerRequest) -> Option<Result<Value, JsonRpcError>> + Send + Sync>;
+
+/// Params of the client's handshake, replayed after a restart.
+#[derive(Default)]
+struct Handshake {
+    initialize: Option<Value>,
+    initialized: Option<Value>,
+}
+
+struct Shared {
+    transport: AppServerTransport,
+    stdin: Mutex<Option<Writer>>,
+    pending: Mutex<PendingMap>,
     notifications: broadcast::Sender<Value>,
+    status: StdMutex<AppServerStatus>,
+    status_tx: broadcast::Sender<AppServerStatus>,
     next_id: AtomicU64,
-    child: Arc<Mutex<Child>>,
+    handshake: Mutex<Handshake>,
+    timeouts: StdMutex<TimeoutPolicy>,
+    shutdown_policy: StdMutex<ShutdownPolicy>,
+    server_requests: broadcast::Sender<ServerRequest>,
+    inbound: Mutex<HashMap<RequestId, ServerRequest>>,
+    request_handler: StdMutex<Option<ServerRequestHandler>>,
+    trace: StdMutex<Option<TraceWriter>>,
+    diagnostics: StdMutex<DiagnosticsLog>,
+    shutdown: Notify,
+    shutting_down: AtomicBool,
+}
+
+/// JSON-RPC client for `codex app-server`, over any [`AppServerTransport`].
+/// A supervisor task restarts the process (or reconnects) with backoff when
+/// it goes away, failing in-flight requests and replaying the `initialize`
+/// handshake on the new connection.
+pub struct AppServerBridge {
+    shared: Arc<Shared>,
 }
 
 impl AppServerBridge {
     pub async fn spawn(cli_path: &Path, extra_args: &[&str]) -> Result<Self> {
-        let mut cmd = Command::new(cli_path);
-        cmd.arg("app-server");
-        cmd.arg("--analytics-default-enabled");
-        for arg in extra_args {
-            cmd.arg(arg);
-        }
-        cmd.stdin(std::process::Stdio::piped());
-        cmd.stdout(std::process::Stdio::piped());
-        cmd.stderr(std::process::Stdio::piped());
-
-        let mut child = cmd
-            .spawn()
-            .with_context(|| format!("failed to spawn app-server from {}", cli_path.display()))?;
-        let stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("failed to capture app-server stdin"))?;
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("failed to capture app-server stdout"))?;
-        let stderr = child
-            .stderr
-            .take()
-            .ok_or_else(|| anyhow!("failed to capture app-server stderr"))?;
-
-        let stdin = Arc::new(Mutex::new(stdin));
-        let pending: Arc<Mutex<HashMap<String, oneshot::Sender<Value>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let (notifications, _) = broadcast::channel(256);
-
-        let pending_clone = Arc::clone(&pending);
-        let notifications_clone = notifications.clone();
-        tokio::spawn(async move {
+        Self::connect(AppServerTransport::Stdio {
+            cli_path: cli_path.to_path_buf(),
+            extra_args: extra_args.iter().map(ToString::to_string).collect(),
+        })
+        .await
+    }
+
+    /// Serves the trace at `trace_path` in place of a real app-server; see
+    /// [`read_trace`] for the format. Nothing is restarted in this mode: the
+    /// bridge stops when the replay ends or it is shut down.
+    pub async fn replay(trace_path: &Path) -> Result<Self> {
+        Self::connect(AppServerTransport::Replay(trace_path.to_path_buf())).await
+    }
+
+    pub async fn connect(transport: AppServerTransport) -> Result<Self> {
+        let shared = Shared::new(transport);
+        let link = shared.open().await?;
+        shared.transition(AppServerState::Ready, link.pid(), |_| {});
+        tokio::spawn(supervise(Arc::clone(&shared), link));
+        info!("app-server bridge connected via {}", shared.transport);
+        Ok(Self { shared })
+    }
+
+    /// Appends every message exchanged with the app-server, and its stderr,
+    /// to the JSONL file at `path`, replacing any trace already running.
+    pub fn start_trace(&self, path: &Path) -> Result<()> {
+        let writer = TraceWriter::create(path)?;
+        *self
+            .shared
+            .trace
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = Some(writer);
+        info!("app-server trace started at {}", path.display());
+        Ok(())
+    }
+
+    /// Stops tracing, returning the file that was being written.
+    pub fn stop_trace(&self) -> Option<PathBuf> {
+        let writer = self
+            .shared
+            .trace
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()?;
+        Some(writer.path().to_path_buf())
+    }
+
+    pub fn trace_path(&self) -> Option<PathBuf> {
+        self.shared
+            .trace
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .as_ref()
+            .map(|writer| writer.path().to_path_buf())
+    }
+
+    pub fn subscribe_notifications(&self) -> broadcast::Receiver<Value> {
+        self.shared.notifications.subscribe()
+    }
+
+    /// Subscribes to the notifications `filter` selects, reporting rather
+    /// than ending on lag.
+    pub fn notification_subscription(
+        &self,
+        filter: NotificationFilter,
+    ) -> NotificationSubscription {
+        NotificationSubscription::new(self.shared.notifications.subscribe(), filter)
+    }
+
+    pub fn subscribe_status(&self) -> broadcast::Receiver<AppServerStatus> {
+        self.shared.status_tx.subscribe()
+    }
+
+    pub fn status(&self) -> AppServerStatus {
+        self.shared.current_status()
+    }
+
+    /// Recent stderr and crash reports, for troubleshooting.
+    pub fn diagnostics(&self) -> AppServerDiagnostics {
+        let status = self.shared.current_status();
+        self.shared
+            .diagnostics
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .snapshot(status, self.shared.transport.to_string())
+    }
+
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Value,
+        request_timeout: Duration,
+        request_id: Option<Value>,
+    ) -> Result<Value> {
+        let deadline = MethodTimeout {
+            timeout_ms: Some(request_timeout.as_millis() as u64),
+            extend_on_activity: false,
+        };
+        self.shared
+            .request(method, params, &deadline, request_id)
+            .await
+    }
+
+    /// Sends a request with the timeout the policy assigns to `method`.
+    pub async fn call(
+        &self,
+        method: &str,
+        params: Value,
+        request_id: Option<Value>,
+    ) -> Result<Value> {
+        let deadline = self.timeout_for(method);
+        self.shared
+            .request(method, params, &deadline, request_id)
+            .await
+    }
+
+    pub fn timeout_for(&self, method: &str) -> MethodTimeout {
+        self.shared
+            .timeouts
+            .lock()
+            .map(|policy| policy.for_method(method).clone())
+            .unwrap_or_else(|err| err.into_inner().for_method(method).clone())
+    }
+
+    pub fn set_timeout_policy(&self, policy: TimeoutPolicy) {
+        if let Ok(mut current) = self.shared.timeouts.lock() {
+            *current = policy;
+        }
+    }
+
+    pub fn set_shutdown_policy(&self, policy: ShutdownPolicy) {
+        if let Ok(mut current) = self.shared.shutdown_policy.lock() {
+            *current = policy;
+        }
+    }
+
+    /// Installs the handler consulted first for every server request.
+    pub fn set_request_handler(&self, handler: ServerRequestHandler) {
+        if let Ok(mut slot) = self.shared.request_handler.lock() {
+            *slot = Some(handler);
+        }
+    }
+
+    /// Subscribes to server requests the handler did not answer. Each must be
+    /// answered with [`AppServerBridge::respond`].
+    pub fn subscribe_server_requests(&self) -> broadcast::Receiver<ServerRequest> {
+        self.shared.server_requests.subscribe()
+    }
+
+    /// Server requests that have not been answered yet.
+    pub async fn outstanding_server_requests(&self) -> Vec<ServerRequest> {
+        self.shared.inbound.lock().await.values().cloned().collect()
+    }
+
+    /// Replies to the server request `id` with a result, or with an error
+    /// object (`{code, message, data?}`).
+    pub async fn respond(&self, id: Value, reply: Result<Value, Value>) -> Result<()> {
+        let id = RequestId::try_from(&id)?;
+        let reply = match reply {
+            Ok(result) => Ok(result),
+            Err(error) => Err(serde_json::from_value(error)
+                .map_err(|err| JsonRpcFrameError::InvalidError(err.to_string()))?),
+        };
+        self.shared.respond(id, reply).await
+    }
+
+    /// Cancels the in-flight request `id`: its caller gets [`RequestCancelled`]
+    /// right away and the app-server is told through `$/cancelRequest`.
+    /// Returns `false` when no such request is pending.
+    pub async fn cancel(&self, id: &Value) -> Result<bool> {
+        let id = RequestId::try_from(id)?;
+        let Some(tx) = self.shared.pending.lock().await.remove(&id) else {
+            return Ok(false);
+        };
+        let _ = tx.send(Err(PendingFailure::Cancelled));
+        debug!("app-server request cancelled id={id}");
+        self.notify("$/cancelRequest", json!({ "id": id })).await?;
+        Ok(true)
+    }
+
+    /// Sends a JSON-RPC notification, which has no id and gets no response.
+    pub async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        if method == "initialized" {
+            self.shared.handshake.lock().await.initialized = Some(params.clone());
+        }
+        self.shared
+            .write_message(&JsonRpcMessage::notification(method, params))
+            .await
+    }
+
+    /// Stops supervision and the app-server as laid out by the
+    /// [`ShutdownPolicy`], then fails whatever requests are still pending.
+    pub async fn shutdown(&self) -> Result<()> {
+        let budget = self.shared.shutdown_policy().budget();
+        let mut status = self.subscribe_status();
+        self.shared.shutting_down.store(true, Ordering::SeqCst);
+        self.shared.shutdown.notify_one();
+        if self.status().state == AppServerState::Stopped {
+            return Ok(());
+        }
+        timeout(budget, async {
+            while let Ok(update) = status.recv().await {
+                if update.state == AppServerState::Stopped {
+                    break;
+                }
+            }
+        })
+        .await
+        .map_err(|_| anyhow!("timed out waiting for app-server to stop"))
+    }
+}
+
+impl Shared {
+    fn new(transport: AppServerTransport) -> Arc<Self> {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CHANNEL_CAPACITY);
+        let (status_tx, _) = broadcast::channel(STATUS_CHANNEL_CAPACITY);
+        let (server_requests, _) = broadcast::channel(SERVER_REQUEST_CHANNEL_CAPACITY);
+        Arc::new(Self {
+            transport,
+            stdin: Mutex::new(None),
+            pending: Mutex::new(HashMap::new()),
+            notifications,
+            status: StdMutex::new(AppServerStatus {
+                state: AppServerState::Starting,
+                pid: None,
+                restarts: 0,
+                attempt: 0,
+                retry_in_ms: None,
+                message: None,
+            }),
+            status_tx,
+            next_id: AtomicU64::new(1),
+            handshake: Mutex::new(Handshake::default()),
+            timeouts: StdMutex::new(TimeoutPolicy::default()),
+            shutdown_policy: StdMutex::new(ShutdownPolicy::default()),
+            server_requests,
+            inbound: Mutex::new(HashMap::new()),
+            request_handler: StdMutex::new(None),
+            trace: StdMutex::new(None),
+            diagnostics: StdMutex::new(DiagnosticsLog::default()),
+            shutdown: Notify::new(),
+            shutting_down: AtomicBool::new(false),
+        })
+    }
+
+    async fn open(self: &Arc<Self>) -> Result<Link> {
+        let Connection {
+            writer,
+            reader,
+            stderr,
+            child,
+            mut tasks,
+        } = self.transport.open().await?;
+        let (closed_tx, closed) = oneshot::channel();
+        tasks.extend(self.attach(writer, reader, stderr, closed_tx).await);
+        Ok(Link {
+            child,
+            tasks,
+            closed,
+        })
+    }
+
+    /// Makes `stdin` the write side and starts reading `stdout` and `stderr`;
+    /// `closed` fires once `stdout` ends. Returns the reader tasks.
+    async fn attach(
+        self: &Arc<Self>,
+        stdin: Writer,
+        stdout: Reader,
+        stderr: Option<Reader>,
+        closed: oneshot::Sender<()>,
+    ) -> Vec<JoinHandle<()>> {
+        *self.stdin.lock().await = Some(stdin);
+
+        let shared = Arc::clone(self);
+        let mut tasks = vec![tokio::spawn(async move {
             let reader = BufReader::new(stdout);
             let mut lines = reader.lines();
             while let Ok(Some(line)) = lines.next_line().await {
                 if line.trim().is_empty() {
                     continue;
                 }
-                match serde_json::from_str::<Value>(&line) {
-                    Ok(value) => {
-                        if let Some(id_key) = jsonrpc_id_to_key(value.get("id")) {
-                            if let Some(tx) = pending_clone.lock().await.remove(&id_key) {
-                                let _ = tx.send(value);
-                                continue;
-                            }
-                        }
-                        let _ = notifications_clone.send(value);
+                shared.trace(TraceDirection::FromServer, &line);
+                match JsonRpcMessage::parse_line(&line) {
+                    Ok(JsonRpcMessage::Request(request)) => {
+                        shared.handle_server_request(request).await
                     }
-                    Err(err) => {
-                        warn!("failed to parse app-server output as json: {err}; raw={line}")
+                    Ok(message @ JsonRpcMessage::Notification(_)) => {
+                        let _ = shared.notifications.send(message.to_value());
                     }
+                    Ok(message) => shared.resolve(message).await,
+                    Err(err) => shared.reject_frame(&line, err).await,
                 }
             }
-            error!("app-server stdout stream ended");
-        });
+            debug!("app-server stdout stream ended");
+            let _ = closed.send(());
+        })];
 
-        tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                warn!("app-server stderr: {line}");
-            }
-        });
+        if let Some(stderr) = stderr {
+            let shared = Arc::clone(self);
+            tasks.push(tokio::spawn(async move {
+                let reader = BufReader::new(stderr);
+                let mut lines = reader.lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    shared.trace(TraceDirection::Stderr, &line);
+                    shared.capture_stderr(&line);
+                }
+            }));
+        }
+        tasks
+    }
 
-        let bridge = Self {
-            stdin,
-            pending,
-            notifications,
-            next_id: AtomicU64::new(1),
-            child: Arc::new(Mutex::new(child)),
-        };
-        info!("app-server bridge started");
-        Ok(bridge)
+    fn capture_stderr(&self, line: &str) {
+        let parsed = StderrLine::parse(line);
+        match parsed.level {
+            LogLevel::Error => error!("app-server stderr: {}", parsed.message),
+            LogLevel::Warn | LogLevel::Unknown => warn!("app-server stderr: {}", parsed.message),
+            LogLevel::Info => info!("app-server stderr: {}", parsed.message),
+            LogLevel::Debug | LogLevel::Trace => debug!("app-server stderr: {}", parsed.message),
+        }
+        self.diagnostics
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push_stderr(parsed);
     }
 
-    pub fn subscribe_notifications(&self) -> broadcast::Receiver<Value> {
-        self.notifications.subscribe()
+    /// Files a crash report for the connection that was just lost.
+    fn record_crash(&self, reason: &str, exit: Option<ExitStatus>, uptime: Duration) {
+        let status = self.current_status();
+        let report = self
+            .diagnostics
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .record_crash(
+                &status,
+                self.transport.to_string(),
+                reason.to_string(),
+                exit,
+                uptime.as_millis() as u64,
+            );
+        let tail = report
+            .stderr_tail
+            .iter()
+            .rev()
+            .take(5)
+            .rev()
+            .map(|line| line.message.as_str())
+            .collect::<Vec<_>>();
+        error!(
+            "{reason} after {}ms; last stderr: {tail:?}",
+            report.uptime_ms
+        );
     }
 
-    pub async fn request(
+    /// Records `line` if a trace is running; a failing trace is dropped.
+    fn trace(&self, direction: TraceDirection, line: &str) {
+        let mut trace = self.trace.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(writer) = trace.as_mut() else {
+            return;
+        };
+        if let Err(err) = writer.record(direction, line) {
+            warn!(
+                "stopping app-server trace at {}: {err:#}",
+                writer.path().display()
+            );
+            *trace = None;
+        }
+    }
+
+    async fn request(
         &self,
         method: &str,
         params: Value,
-        request_timeout: Duration,
+        deadline: &MethodTimeout,
         request_id: Option<Value>,
     ) -> Result<Value> {
-        let id = request_id.unwrap_or_else(|| json!(self.next_id.fetch_add(1, Ordering::Relaxed)));
-        let id_key =
-            jsonrpc_id_to_key(Some(&id)).ok_or_else(|| anyhow!("invalid jsonrpc request id"))?;
-        let envelope = json!({
-            "jsonrpc": "2.0",
-            "id": id,
-            "method": method,
-            "params": params
-        });
-        let line = serde_json::to_string(&envelope)?;
+        let correlation = timeouts::correlation_ids(&params);
+        // Subscribe before sending so no related notification is missed.
+        let mut activity = (deadline.extend_on_activity && !correlation.is_empty())
+            .then(|| self.notifications.subscribe());
+        let id = match request_id {
+            Some(id) => RequestId::try_from(&id)?,
+            None => RequestId::Number(self.next_id.fetch_add(1, Ordering::Relaxed) as i64),
+        };
+        let id_key = id.clone();
         let (tx, rx) = oneshot::channel();
-        self.pending.lock().await.insert(id_key.clone(), tx);
-
-        {
-            let mut stdin = self.stdin.lock().await;
-            stdin
-                .write_all(line.as_bytes())
-                .await
-                .context("failed writing request line to app-server")?;
-            stdin
-                .write_all(b"\n")
-                .await
-                .context("failed writing newline to app-server")?;
-            stdin
-                .flush()
-                .await
-                .context("failed flushing app-server stdin")?;
+        self.pending.lock().await.insert(id.clone(), tx);
+
+        let message = JsonRpcMessage::request(id, method, params.clone());
+        if let Err(err) = self.write_message(&message).await {
+            self.pending.lock().await.remove(&id_key);
+            return Err(err);
         }
         debug!("app-server request sent method={method} id={id_key}");
 
-        match timeout(request_timeout, rx).await {
-            Ok(Ok(value)) => Ok(value),
+        let request_timeout = deadline.duration();
+        let started = Instant::now();
+        let mut expires = request_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut rx = rx;
+        let outcome = loop {
+            tokio::select! {
+                response = &mut rx => break Ok(response),
+                _ = sleep_until(expires) => break Err(()),
+                notification = recv_activity(&mut activity) => match notification {
+                    Ok(notification) if timeouts::is_related(&notification, &correlation) => {
+                        expires = request_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+                    }
+                    Ok(_) => {}
+                    // Missed notifications may have been related; err on the side of waiting.
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        expires = request_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => activity = None,
+                },
+            }
+        };
+
+        match outcome {
+            Ok(Ok(Ok(value))) => {
+                if method == "initialize" && value.get("error").is_none() {
+                    self.handshake.lock().await.initialize = Some(params);
+                }
+                Ok(value)
+            }
+            Ok(Ok(Err(PendingFailure::Failed(message)))) => {
+                Err(anyhow!("{message} (id={id_key} method={method})"))
+            }
+            Ok(Ok(Err(PendingFailure::Cancelled))) => Err(RequestCancelled {
+                id: id_key.to_string(),
+                method: method.to_string(),
+            }
+            .into()),
             Ok(Err(_)) => {
                 self.pending.lock().await.remove(&id_key);
                 Err(anyhow!(
@@ -150,25 +581,387 @@ impl AppServerBridge {
             Err(_) => {
                 self.pending.lock().await.remove(&id_key);
                 Err(anyhow!(
-                    "app-server timeout waiting for response id={id_key} method={method}"
+                    "app-server timeout waiting for response id={id_key} method={method} after {}ms",
+                    started.elapsed().as_millis()
                 ))
             }
         }
     }
 
-    pub async fn shutdown(&self) -> Result<()> {
-        let mut child = self.child.lock().await;
-        child.kill().await?;
+    async fn write_message(&self, message: &JsonRpcMessage) -> Result<()> {
+        self.write_line(&serde_json::to_string(message)?).await
+    }
+
+    async fn write_line(&self, line: &str) -> Result<()> {
+        let mut stdin = self.stdin.lock().await;
+        let Some(stdin) = stdin.as_mut() else {
+            return Err(anyhow!(
+                "app-server is not running (state: {:?})",
+                self.current_status().state
+            ));
+        };
+        stdin
+            .write_all(line.as_bytes())
+            .await
+            .context("failed writing request line to app-server")?;
+        stdin
+            .write_all(b"\n")
+            .await
+            .context("failed writing newline to app-server")?;
+        stdin
+            .flush()
+            .await
+            .context("failed flushing app-server stdin")?;
+        self.trace(TraceDirection::ToServer, line);
+        Ok(())
+    }
+
+    /// Hands a response or error response to the request waiting on it.
+    async fn resolve(&self, message: JsonRpcMessage) {
+        let Some(id) = message.id() else {
+            warn!(
+                "app-server reported an error for no request: {}",
+                message.to_value()
+            );
+            return;
+        };
+        match self.pending.lock().await.remove(id) {
+            Some(tx) => {
+                let _ = tx.send(Ok(message.to_value()));
+            }
+            None => debug!("dropping app-server response for unknown request id={id}"),
+        }
+    }
+
+    /// Logs an invalid frame and fails the request it answers, if any.
+    async fn reject_frame(&self, line: &str, err: JsonRpcFrameError) {
+        warn!("rejecting app-server frame: {err}; raw={line}");
+        let id = serde_json::from_str::<Value>(line)
+            .ok()
+            .and_then(|value| RequestId::try_from(value.get("id")?).ok());
+        let Some(id) = id else {
+            return;
+        };
+        if let Some(tx) = self.pending.lock().await.remove(&id) {
+            let _ = tx.send(Err(PendingFailure::Failed(format!(
+                "app-server sent an invalid response: {err}"
+            ))));
+        }
+    }
+
+    async fn handle_server_request(&self, request: JsonRpcRequest) {
+        let request = ServerRequest {
+            id: request.id,
+            method: request.method,
+            params: request.params.unwrap_or(Value::Null),
+        };
+        debug!(
+            "app-server request received method={} id={}",
+            request.method, request.id
+        );
+        self.inbound
+            .lock()
+            .await
+            .insert(request.id.clone(), request.clone());
+        let handled = self
+            .request_handler
+            .lock()
+            .ok()
+            .and_then(|handler| handler.as_ref().and_then(|handler| handler(&request)));
+        let reply = match handled {
+            Some(reply) => reply,
+            None if self.server_requests.send(request.clone()).is_ok() => return,
+            None => Err(JsonRpcError::new(
+                JsonRpcError::METHOD_NOT_FOUND,
+                format!("no handler for server request '{}'", request.method),
+            )),
+        };
+        if let Err(err) = self.respond(request.id, reply).await {
+            warn!("failed to answer app-server request: {err:#}");
+        }
+    }
+
+    async fn respond(&self, id: RequestId, reply: Result<Value, JsonRpcError>) -> Result<()> {
+        if self.inbound.lock().await.remove(&id).is_none() {
+            return Err(anyhow!("no outstanding app-server request with id={id}"));
+        }
+        self.write_message(&JsonRpcMessage::reply(id, reply)).await
+    }
+
+    /// Resolves every in-flight request with `message` as its error.
+    async fn fail_pending(&self, message: &str) {
+        let pending = std::mem::take(&mut *self.pending.lock().await);
+        for (_, tx) in pending {
+            let _ = tx.send(Err(PendingFailure::Failed(message.to_string())));
+        }
+    }
+
+    /// Re-sends the last successful `initialize` (and `initialized`) so the
+    /// restarted process is in the state the client set up.
+    async fn replay_handshake(&self) -> Result<()> {
+        let (initialize, initialized) = {
+            let handshake = self.handshake.lock().await;
+            (handshake.initialize.clone(), handshake.initialized.clone())
+        };
+        let Some(params) = initialize else {
+            return Ok(());
+        };
+        let response = self
+            .request(
+                "initialize",
+                params,
+                &MethodTimeout {
+                    timeout_ms: Some(HANDSHAKE_TIMEOUT.as_millis() as u64),
+                    extend_on_activity: false,
+                },
+                None,
+            )
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("app-server rejected initialize: {error}"));
+        }
+        if let Some(params) = initialized {
+            self.write_message(&JsonRpcMessage::notification("initialized", params))
+                .await?;
+        }
         Ok(())
     }
+
+    fn shutdown_policy(&self) -> ShutdownPolicy {
+        self.shutdown_policy
+            .lock()
+            .map(|policy| policy.clone())
+            .unwrap_or_else(|err| err.into_inner().clone())
+    }
+
+    /// Flushes and closes the app-server's input, which it takes as the cue
+    /// to finish up and exit.
+    async fn close_stdin(&self) {
+        if let Some(mut stdin) = self.stdin.lock().await.take() {
+            let _ = stdin.shutdown().await;
+        }
+    }
+
+    /// Runs the shutdown sequence, escalating a stage at a time until the
+    /// app-server is gone.
+    async fn stop(&self, link: &mut Link) -> (String, Option<ExitStatus>) {
+        let policy = self.shutdown_policy();
+        self.transition(AppServerState::Stopping, link.pid(), |_| {});
+        if let Some(method) = &policy.request_method {
+            let deadline = MethodTimeout {
+                timeout_ms: Some(policy.request_timeout_ms),
+                extend_on_activity: false,
+            };
+            if let Err(err) = self.request(method, json!({}), &deadline, None).await {
+                warn!("app-server shutdown request failed: {err:#}");
+            }
+        }
+        self.close_stdin().await;
+        if let Ok(lost) = timeout(policy.exit_timeout(), link.lost()).await {
+            info!("app-server exited after its input was closed");
+            return lost;
+        }
+        if let Some(pid) = link.pid() {
+            if shutdown::terminate(pid) {
+                warn!(
+                    "app-server did not exit within {}ms of its input closing, sent SIGTERM",
+                    policy.exit_timeout_ms
+                );
+                if let Ok(lost) = timeout(policy.terminate_timeout(), link.lost()).await {
+                    return lost;
+                }
+            }
+        }
+        warn!("app-server did not stop, killing it");
+        link.abort();
+        link.lost().await
+    }
+
+    fn current_status(&self) -> AppServerStatus {
+        self.status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or_else(|err| err.into_inner().clone())
+    }
+
+    fn transition(
+        &self,
+        state: AppServerState,
+        pid: Option<u32>,
+        update: impl FnOnce(&mut AppServerStatus),
+    ) {
+        let snapshot = {
+            let mut status = self.status.lock().unwrap_or_else(|err| err.into_inner());
+            status.state = state;
+            status.pid = pid;
+            status.retry_in_ms = None;
+            status.message = None;
+            if state != AppServerState::Restarting && state != AppServerState::Starting {
+                status.attempt = 0;
+            }
+            update(&mut status);
+            status.clone()
+        };
+        let _ = self.status_tx.send(snapshot);
+    }
+}
+
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn recv_activity(
+    activity: &mut Option<broadcast::Receiver<Value>>,
+) -> Result<Value, broadcast::error::RecvError> {
+    match activity {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn restart_delay(attempt: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1).min(16));
+    RESTART_BACKOFF_INITIAL
+        .saturating_mul(factor)
+        .min(RESTART_BACKOFF_MAX)
+}
+
+/// What keeps one connection to the app-server alive.
+struct Link {
+    child: Option<Child>,
+    tasks: Vec<JoinHandle<()>>,
+    /// Fires when the app-server's output ends.
+    closed: oneshot::Receiver<()>,
+}
+
+impl Link {
+    fn pid(&self) -> Option<u32> {
+        self.child.as_ref().and_then(Child::id)
+    }
+
+    /// Waits for the process to exit, or for the connection to close.
+    async fn lost(&mut self) -> (String, Option<ExitStatus>) {
+        match &mut self.child {
+            Some(child) => match child.wait().await {
+                Ok(status) => (format!("app-server exited with {status}"), Some(status)),
+                Err(err) => (format!("failed waiting for app-server: {err}"), None),
+            },
+            None => {
+                let _ = (&mut self.closed).await;
+                ("app-server connection closed".to_string(), None)
+            }
+        }
+    }
+
+    /// Waits briefly for the reader to reach the end of the exited process's
+    /// output, so responses written just before the exit still resolve.
+    async fn drain(&mut self) {
+        // Without a child, `lost` already waited for (and consumed) `closed`.
+        if self.child.is_some() {
+            let _ = timeout(OUTPUT_DRAIN_TIMEOUT, &mut self.closed).await;
+        }
+    }
+
+    /// Kills the process or drops the connection; [`Link::lost`] then returns.
+    fn abort(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.start_kill();
+        }
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
 }
 
-fn jsonrpc_id_to_key(id: Option<&Value>) -> Option<String> {
-    let id = id?;
-    match id {
-        Value::String(value) => Some(format!("s:{value}")),
-        Value::Number(value) => Some(format!("n:{value}")),
-        Value::Bool(value) => Some(format!("b:{value}")),
-        _ => Some(format!("j:{}", id)),
+async fn supervise(shared: Arc<Shared>, mut link: Link) {
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let (reason, exit) = tokio::select! {
+            lost = link.lost() => lost,
+            _ = shared.shutdown.notified() => shared.stop(&mut link).await,
+        };
+        link.drain().await;
+        link.abort();
+        *shared.stdin.lock().await = None;
+        if shared.shutting_down.load(Ordering::SeqCst) {
+            shared.fail_pending("app-server was shut down").await;
+            shared.transition(AppServerState::Stopped, None, |_| {});
+            info!("app-server bridge stopped");
+            return;
+        }
+        if !shared.transport.reconnects() {
+            shared.fail_pending(&reason).await;
+            shared.transition(AppServerState::Stopped, None, |status| {
+                status.message = Some(reason.clone());
+            });
+            info!("app-server bridge stopped: {reason}");
+            return;
+        }
+        shared.record_crash(&reason, exit, started.elapsed());
+        shared.transition(AppServerState::Crashed, None, |status| {
+            status.message = Some(reason.clone());
+        });
+        shared.fail_pending(&reason).await;
+        // Server requests cannot be answered once their process is gone.
+        shared.inbound.lock().await.clear();
+        if started.elapsed() >= STABLE_RUN {
+            attempt = 0;
+        }
+
+        loop {
+            attempt += 1;
+            let delay = restart_delay(attempt);
+            shared.transition(AppServerState::Restarting, None, |status| {
+                status.attempt = attempt;
+                status.retry_in_ms = Some(delay.as_millis() as u64);
+            });
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shared.shutdown.notified() => {
+                    shared.transition(AppServerState::Stopped, None, |_| {});
+                    return;
+                }
+            }
+            shared.transition(AppServerState::Starting, None, |status| {
+                status.attempt = attempt;
+            });
+            match shared.open().await {
+                Ok(next) => {
+                    link = next;
+                    break;
+                }
+                Err(err) => {
+                    error!("app-server restart failed: {err:#}");
+                    shared.transition(AppServerState::Crashed, None, |status| {
+                        status.message = Some(format!("{err:#}"));
+                    });
+                }
+            }
+        }
+
+        match shared.replay_handshake().await {
+            Ok(()) => {
+                info!("app-server restarted after {attempt} attempt(s)");
+                shared.transition(AppServerState::Ready, link.pid(), |status| {
+                    status.restarts += 1;
+                });
+            }
+            Err(err) => {
+                // The loss is picked up at the top of the loop and retried.
+                error!("app-server handshake replay failed: {err:#}");
+                link.abort();
+            }
+        }
     }
 }
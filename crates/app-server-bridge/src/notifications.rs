@@ -0,0 +1,91 @@
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+/// Selects notifications by method prefix; no prefixes selects everything.
+#[derive(Debug, Clone, Default)]
+pub struct NotificationFilter {
+    method_prefixes: Vec<String>,
+}
+
+impl NotificationFilter {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Matches methods starting with any of `prefixes`, e.g. `"turn/"`.
+    pub fn method_prefixes<I, S>(prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            method_prefixes: prefixes.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn matches(&self, notification: &Value) -> bool {
+        if self.method_prefixes.is_empty() {
+            return true;
+        }
+        let method = notification
+            .get("method")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        self.method_prefixes
+            .iter()
+            .any(|prefix| method.starts_with(prefix.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationEvent {
+    Notification(Value),
+    /// The subscriber fell behind and `dropped` notifications were discarded,
+    /// possibly including some its filter would have skipped. Delivery goes
+    /// on with the oldest notification still buffered; state derived from
+    /// notifications should be resynced.
+    Lagged {
+        dropped: u64,
+    },
+}
+
+/// Notifications for one subscriber that survive the subscriber falling
+/// behind, unlike a bare `broadcast::Receiver`.
+pub struct NotificationSubscription {
+    receiver: broadcast::Receiver<Value>,
+    filter: NotificationFilter,
+    dropped: u64,
+}
+
+impl NotificationSubscription {
+    pub(crate) fn new(receiver: broadcast::Receiver<Value>, filter: NotificationFilter) -> Self {
+        Self {
+            receiver,
+            filter,
+            dropped: 0,
+        }
+    }
+
+    /// The next matching notification or lag report; `None` once the bridge
+    /// is gone.
+    pub async fn recv(&mut self) -> Option<NotificationEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(notification) if self.filter.matches(&notification) => {
+                    return Some(NotificationEvent::Notification(notification));
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(dropped)) => {
+                    self.dropped += dropped;
+                    return Some(NotificationEvent::Lagged { dropped });
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Notifications dropped over the life of this subscription.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How [`crate::AppServerBridge::shutdown`] stops the app-server: optionally
+/// a shutdown request, then closing its stdin and waiting for it to exit,
+/// then SIGTERM, then SIGKILL. Each stage only runs if the previous one did
+/// not end the process within its timeout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ShutdownPolicy {
+    /// Request sent before stdin is closed; `None` goes straight to closing.
+    pub request_method: Option<String>,
+    pub request_timeout_ms: u64,
+    /// Wait for the process to exit on its own once stdin is closed.
+    pub exit_timeout_ms: u64,
+    /// Wait after SIGTERM before SIGKILL.
+    pub terminate_timeout_ms: u64,
+}
+
+impl Default for ShutdownPolicy {
+    fn default() -> Self {
+        Self {
+            request_method: None,
+            request_timeout_ms: 2_000,
+            exit_timeout_ms: 5_000,
+            terminate_timeout_ms: 2_000,
+        }
+    }
+}
+
+impl ShutdownPolicy {
+    pub(crate) fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+
+    pub(crate) fn exit_timeout(&self) -> Duration {
+        Duration::from_millis(self.exit_timeout_ms)
+    }
+
+    pub(crate) fn terminate_timeout(&self) -> Duration {
+        Duration::from_millis(self.terminate_timeout_ms)
+    }
+
+    /// Upper bound on the whole sequence, SIGKILL included.
+    pub(crate) fn budget(&self) -> Duration {
+        let request = match self.request_method {
+            Some(_) => self.request_timeout(),
+            None => Duration::ZERO,
+        };
+        request + self.exit_timeout() + self.terminate_timeout() + Duration::from_secs(2)
+    }
+}
+
+/// Asks `pid` to exit with SIGTERM. Elsewhere there is no polite signal and
+/// the caller escalates to killing the process.
+#[cfg(unix)]
+pub(crate) fn terminate(pid: u32) -> bool {
+    // SAFETY: kill(2) has no memory-safety preconditions.
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) == 0 }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn terminate(_pid: u32) -> bool {
+    false
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Params that tie notifications to the request that caused them.
+const CORRELATION_KEYS: &[&str] = &["threadId", "turnId", "conversationId"];
+
+/// How long to wait for the response to one method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodTimeout {
+    /// `None` waits until the response arrives or the request is cancelled.
+    pub timeout_ms: Option<u64>,
+    /// Restarts the deadline whenever a notification sharing the request's
+    /// `threadId`, `turnId` or `conversationId` arrives.
+    #[serde(default)]
+    pub extend_on_activity: bool,
+}
+
+impl MethodTimeout {
+    const fn after(timeout_ms: u64, extend_on_activity: bool) -> Self {
+        Self {
+            timeout_ms: Some(timeout_ms),
+            extend_on_activity,
+        }
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.timeout_ms.map(Duration::from_millis)
+    }
+}
+
+/// Timeouts keyed by method. A key ending in `/` applies to every method
+/// under that prefix; exact keys win over prefixes, longer prefixes over
+/// shorter ones, and `default` covers everything else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeoutPolicy {
+    pub default: MethodTimeout,
+    pub methods: BTreeMap<String, MethodTimeout>,
+}
+
+impl Default for TimeoutPolicy {
+    fn default() -> Self {
+        let methods = [
+            ("config/", MethodTimeout::after(15_000, false)),
+            ("getAuthStatus", MethodTimeout::after(15_000, false)),
+            ("skills/list", MethodTimeout::after(30_000, false)),
+            ("thread/", MethodTimeout::after(60_000, true)),
+            (
+                "turn/",
+                MethodTimeout {
+                    timeout_ms: None,
+                    extend_on_activity: false,
+                },
+            ),
+            ("turn/interrupt", MethodTimeout::after(30_000, false)),
+        ];
+        Self {
+            default: MethodTimeout::after(120_000, true),
+            methods: methods
+                .into_iter()
+                .map(|(method, timeout)| (method.to_string(), timeout))
+                .collect(),
+        }
+    }
+}
+
+impl TimeoutPolicy {
+    pub fn for_method(&self, method: &str) -> &MethodTimeout {
+        if let Some(timeout) = self.methods.get(method) {
+            return timeout;
+        }
+        self.methods
+            .iter()
+            .filter(|(key, _)| key.ends_with('/') && method.starts_with(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, timeout)| timeout)
+            .unwrap_or(&self.default)
+    }
+
+    /// Applies `{ "default": {...}, "methods": { "<method>": {...} } }` on
+    /// top of this policy; listed methods replace their existing entries.
+    pub fn apply_overrides(&mut self, overrides: &Value) -> Result<()> {
+        if let Some(default) = overrides.get("default") {
+            self.default = serde_json::from_value(default.clone())?;
+        }
+        if let Some(methods) = overrides.get("methods") {
+            let methods: BTreeMap<String, MethodTimeout> = serde_json::from_value(methods.clone())?;
+            self.methods.extend(methods);
+        }
+        Ok(())
+    }
+}
+
+/// The correlation ids present in a request's params.
+pub(crate) fn correlation_ids(params: &Value) -> Vec<(&'static str, Value)> {
+    CORRELATION_KEYS
+        .iter()
+        .filter_map(|key| Some((*key, params.get(*key)?.clone())))
+        .collect()
+}
+
+/// Whether `notification` carries one of `ids`, at the top level of its
+/// params or one object below it.
+pub(crate) fn is_related(notification: &Value, ids: &[(&'static str, Value)]) -> bool {
+    let Some(params) = notification.get("params") else {
+        return false;
+    };
+    let matches = |object: &Value| ids.iter().any(|(key, id)| object.get(*key) == Some(id));
+    matches(params)
+        || params
+            .as_object()
+            .is_some_and(|object| object.values().any(matches))
+}
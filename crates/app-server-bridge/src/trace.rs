@@ -0,0 +1,267 @@
+use crate::unix_time_ms;
+use anyhow::{Context, Result};
+use host_api::{JsonRpcError, RequestId};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Longest pause replay keeps between two recorded messages; idle stretches
+/// in a recording are shortened to this.
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TraceDirection {
+    /// Written by the bridge to the app-server's stdin.
+    ToServer,
+    /// Read from the app-server's stdout.
+    FromServer,
+    /// A line of the app-server's stderr.
+    Stderr,
+}
+
+/// One line of a trace file. JSON-RPC traffic is stored parsed in `message`;
+/// stderr and unparseable output are stored verbatim in `line`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceRecord {
+    pub timestamp_ms: u64,
+    pub direction: TraceDirection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<String>,
+}
+
+impl TraceRecord {
+    fn new(direction: TraceDirection, line: &str) -> Self {
+        let message = match direction {
+            TraceDirection::Stderr => None,
+            _ => serde_json::from_str(line).ok(),
+        };
+        Self {
+            timestamp_ms: unix_time_ms(),
+            direction,
+            line: message.is_none().then(|| line.to_string()),
+            message,
+        }
+    }
+
+    fn text(&self) -> String {
+        match &self.message {
+            Some(message) => message.to_string(),
+            None => self.line.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Appends records to a JSONL trace file, flushing after every line so a
+/// crash loses nothing. New files are readable by the user only.
+pub(crate) struct TraceWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+impl TraceWriter {
+    pub(crate) fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let mut options = File::options();
+        options.create(true).append(true);
+        // Traces hold full payloads, including account and auth traffic.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options
+            .open(path)
+            .with_context(|| format!("failed to open trace file {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            out: BufWriter::new(file),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn record(&mut self, direction: TraceDirection, line: &str) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &TraceRecord::new(direction, line))?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+pub fn read_trace(path: &Path) -> Result<Vec<TraceRecord>> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open trace {}", path.display()))?;
+    let mut records = Vec::new();
+    for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .with_context(|| format!("invalid trace record on line {}", index + 1))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Plays the app-server side of `records` against a live client.
+///
+/// Server output is written with its recorded pacing. At each recorded client
+/// request or response, playback waits until the client sends a matching
+/// message: requests match by method, in recorded order, and responses to
+/// server requests by id. Response ids are rewritten to the ids the client
+/// actually used. Client requests the trace has no counterpart for get a
+/// `-32601` error. Returns when the client closes its side.
+pub(crate) async fn serve_replay<R, W, E>(
+    records: Vec<TraceRecord>,
+    input: R,
+    mut output: W,
+    mut stderr: E,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    E: AsyncWrite + Unpin,
+{
+    let mut input = BufReader::new(input).lines();
+    let mut replay = Replay {
+        consumed: vec![false; records.len()],
+        ids: HashMap::new(),
+        records: &records,
+    };
+    let mut previous: Option<u64> = None;
+    for (index, record) in records.iter().enumerate() {
+        match record.direction {
+            TraceDirection::FromServer | TraceDirection::Stderr => {
+                if let Some(previous) = previous {
+                    let gap = Duration::from_millis(record.timestamp_ms.saturating_sub(previous));
+                    tokio::time::sleep(gap.min(MAX_REPLAY_GAP)).await;
+                }
+                if record.direction == TraceDirection::Stderr {
+                    write_line(&mut stderr, &record.text()).await?;
+                } else {
+                    write_line(&mut output, &replay.rewrite(record)).await?;
+                }
+            }
+            TraceDirection::ToServer => {
+                let awaited = record
+                    .message
+                    .as_ref()
+                    .is_some_and(|message| message.get("id").is_some());
+                while awaited && !replay.consumed[index] {
+                    let Some(line) = input.next_line().await? else {
+                        return Ok(());
+                    };
+                    if let Some(reply) = replay.accept(index, &line) {
+                        write_line(&mut output, &reply.to_string()).await?;
+                    }
+                }
+            }
+        }
+        previous = Some(record.timestamp_ms);
+    }
+    while let Some(line) = input.next_line().await? {
+        if let Some(reply) = replay.accept(records.len(), &line) {
+            write_line(&mut output, &reply.to_string()).await?;
+        }
+    }
+    Ok(())
+}
+
+struct Replay<'a> {
+    records: &'a [TraceRecord],
+    consumed: Vec<bool>,
+    /// Recorded request id -> id the live client used.
+    ids: HashMap<RequestId, Value>,
+}
+
+impl Replay<'_> {
+    /// Matches a live client message against the recorded client messages
+    /// from `from` on, returning an error reply for unmatched requests.
+    fn accept(&mut self, from: usize, line: &str) -> Option<Value> {
+        let message: Value = serde_json::from_str(line).ok()?;
+        let method = message.get("method").and_then(Value::as_str);
+        let id = message.get("id");
+        let found = (from..self.records.len()).find(|&index| {
+            let record = &self.records[index];
+            let Some(recorded) = &record.message else {
+                return false;
+            };
+            if self.consumed[index] || record.direction != TraceDirection::ToServer {
+                return false;
+            }
+            let recorded_method = recorded.get("method").and_then(Value::as_str);
+            match (method, id) {
+                (Some(_), _) => {
+                    recorded_method == method && recorded.get("id").is_some() == id.is_some()
+                }
+                (None, Some(id)) => recorded_method.is_none() && recorded.get("id") == Some(id),
+                (None, None) => false,
+            }
+        });
+        match (found, method, id) {
+            (Some(index), _, _) => {
+                self.consumed[index] = true;
+                if let (Some(_), Some(id)) = (method, id) {
+                    let recorded_id = self.records[index]
+                        .message
+                        .as_ref()
+                        .and_then(|recorded| RequestId::try_from(recorded.get("id")?).ok());
+                    if let Some(recorded_id) = recorded_id {
+                        self.ids.insert(recorded_id, id.clone());
+                    }
+                }
+                None
+            }
+            (None, Some(method), Some(id)) => Some(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": JsonRpcError::METHOD_NOT_FOUND,
+                    "message": format!("no recorded response for '{method}' in the replayed trace"),
+                },
+            })),
+            _ => None,
+        }
+    }
+
+    /// Serializes recorded server output, pointing responses at live ids.
+    fn rewrite(&self, record: &TraceRecord) -> String {
+        let Some(message) = &record.message else {
+            return record.text();
+        };
+        let live_id = match message.get("method") {
+            Some(_) => None,
+            None => message
+                .get("id")
+                .and_then(|id| RequestId::try_from(id).ok())
+                .and_then(|id| self.ids.get(&id)),
+        };
+        match live_id {
+            Some(id) => {
+                let mut message = message.clone();
+                message["id"] = id.clone();
+                message.to_string()
+            }
+            None => message.to_string(),
+        }
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(out: &mut W, line: &str) -> Result<()> {
+    out.write_all(line.as_bytes()).await?;
+    out.write_all(b"\n").await?;
+    out.flush().await?;
+    Ok(())
+}
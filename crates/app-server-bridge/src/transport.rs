@@ -0,0 +1,231 @@
+use crate::trace::{self, read_trace};
+use anyhow::{anyhow, Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, warn};
+
+/// Capacity of the in-memory pipes that adapt non-stdio transports.
+const PIPE_CAPACITY: usize = 1 << 20;
+
+pub(crate) type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+pub(crate) type Reader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// How the bridge reaches the app-server. Every transport carries the same
+/// newline-delimited JSON-RPC; over WebSocket each text message is one frame.
+#[derive(Debug, Clone)]
+pub enum AppServerTransport {
+    /// Launches `<cli_path> app-server` and talks over its stdio.
+    Stdio {
+        cli_path: PathBuf,
+        extra_args: Vec<String>,
+    },
+    /// Connects to a running app-server listening on a Unix domain socket.
+    #[cfg(unix)]
+    UnixSocket(PathBuf),
+    /// Connects to a running app-server at a `ws://` URL.
+    WebSocket(String),
+    /// Serves a recorded trace; see [`crate::read_trace`].
+    Replay(PathBuf),
+}
+
+impl AppServerTransport {
+    /// Parses `unix:<path>` (also `unix://<path>`) or a `ws://` URL.
+    pub fn parse_url(url: &str) -> Result<Self> {
+        if let Some(path) = url.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Self::UnixSocket(PathBuf::from(
+                path.strip_prefix("//").unwrap_or(path),
+            )));
+            #[cfg(not(unix))]
+            return Err(anyhow!("unix sockets are not supported on this platform"));
+        }
+        if url.starts_with("ws://") {
+            return Ok(Self::WebSocket(url.to_string()));
+        }
+        if url.starts_with("wss://") {
+            return Err(anyhow!(
+                "wss:// is not supported, connect to a local ws:// URL"
+            ));
+        }
+        Err(anyhow!(
+            "unsupported app-server url {url:?}, expected unix:<path> or ws://<host>:<port>"
+        ))
+    }
+
+    /// Whether a lost connection is worth re-establishing.
+    pub(crate) fn reconnects(&self) -> bool {
+        !matches!(self, Self::Replay(_))
+    }
+
+    pub(crate) async fn open(&self) -> Result<Connection> {
+        match self {
+            Self::Stdio {
+                cli_path,
+                extra_args,
+            } => spawn_cli(cli_path, extra_args),
+            #[cfg(unix)]
+            Self::UnixSocket(path) => {
+                let stream = tokio::net::UnixStream::connect(path)
+                    .await
+                    .with_context(|| format!("failed to connect to {}", path.display()))?;
+                let (reader, writer) = stream.into_split();
+                Ok(Connection::stream(Box::new(reader), Box::new(writer)))
+            }
+            Self::WebSocket(url) => connect_websocket(url).await,
+            Self::Replay(path) => open_replay(path),
+        }
+    }
+}
+
+impl fmt::Display for AppServerTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stdio { cli_path, .. } => write!(f, "stdio:{}", cli_path.display()),
+            #[cfg(unix)]
+            Self::UnixSocket(path) => write!(f, "unix:{}", path.display()),
+            Self::WebSocket(url) => f.write_str(url),
+            Self::Replay(path) => write!(f, "replay:{}", path.display()),
+        }
+    }
+}
+
+/// An open transport: the line streams plus whatever keeps them alive.
+pub(crate) struct Connection {
+    pub(crate) writer: Writer,
+    pub(crate) reader: Reader,
+    pub(crate) stderr: Option<Reader>,
+    pub(crate) child: Option<Child>,
+    /// Adapter tasks, aborted when the connection is closed.
+    pub(crate) tasks: Vec<JoinHandle<()>>,
+}
+
+impl Connection {
+    fn stream(reader: Reader, writer: Writer) -> Self {
+        Self {
+            writer,
+            reader,
+            stderr: None,
+            child: None,
+            tasks: Vec::new(),
+        }
+    }
+}
+
+fn spawn_cli(cli_path: &Path, extra_args: &[String]) -> Result<Connection> {
+    let mut cmd = Command::new(cli_path);
+    cmd.arg("app-server");
+    cmd.arg("--analytics-default-enabled");
+    for arg in extra_args {
+        cmd.arg(arg);
+    }
+    cmd.stdin(std::process::Stdio::piped());
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::piped());
+    cmd.kill_on_drop(true);
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("failed to spawn app-server from {}", cli_path.display()))?;
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("failed to capture app-server stdin"))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("failed to capture app-server stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("failed to capture app-server stderr"))?;
+    Ok(Connection {
+        writer: Box::new(stdin),
+        reader: Box::new(stdout),
+        stderr: Some(Box::new(stderr)),
+        child: Some(child),
+        tasks: Vec::new(),
+    })
+}
+
+async fn connect_websocket(url: &str) -> Result<Connection> {
+    let (socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .with_context(|| format!("failed to connect to {url}"))?;
+    let (bridge_side, pump_side) = tokio::io::duplex(PIPE_CAPACITY);
+    let (reader, writer) = tokio::io::split(bridge_side);
+    let mut connection = Connection::stream(Box::new(reader), Box::new(writer));
+    connection
+        .tasks
+        .push(tokio::spawn(pump_websocket(socket, pump_side)));
+    Ok(connection)
+}
+
+/// Moves lines written by the bridge into text messages and text messages
+/// into lines, until either side closes.
+async fn pump_websocket<S>(socket: tokio_tungstenite::WebSocketStream<S>, pipe: DuplexStream)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut sink, mut messages) = socket.split();
+    let (pipe_reader, mut pipe_writer) = tokio::io::split(pipe);
+    let mut lines = BufReader::new(pipe_reader).lines();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    let _ = sink.close().await;
+                    return;
+                };
+                if let Err(err) = sink.send(Message::text(line)).await {
+                    warn!("app-server websocket send failed: {err}");
+                    return;
+                }
+            }
+            message = messages.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text.to_string(),
+                    Some(Ok(Message::Binary(bytes))) => String::from_utf8_lossy(&bytes).into_owned(),
+                    Some(Ok(Message::Close(_))) | None => return,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => {
+                        warn!("app-server websocket receive failed: {err}");
+                        return;
+                    }
+                };
+                let written = async {
+                    pipe_writer.write_all(text.trim_end().as_bytes()).await?;
+                    pipe_writer.write_all(b"\n").await
+                };
+                if written.await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn open_replay(path: &Path) -> Result<Connection> {
+    let records = read_trace(path)?;
+    let (client, server_input) = tokio::io::duplex(PIPE_CAPACITY);
+    let (server_output, stdout) = tokio::io::duplex(PIPE_CAPACITY);
+    let (server_stderr, stderr) = tokio::io::duplex(PIPE_CAPACITY);
+    let replay = tokio::spawn(async move {
+        if let Err(err) =
+            trace::serve_replay(records, server_input, server_output, server_stderr).await
+        {
+            error!("app-server replay failed: {err:#}");
+        }
+    });
+    Ok(Connection {
+        writer: Box::new(client),
+        reader: Box::new(stdout),
+        stderr: Some(Box::new(stderr)),
+        child: None,
+        tasks: vec![replay],
+    })
+}
@@ -0,0 +1,483 @@
+use app_server_bridge::{
+    AppServerBridge, AppServerState, LogLevel, MethodTimeout, NotificationEvent,
+    NotificationFilter, RequestCancelled, ShutdownPolicy, TimeoutPolicy, TraceDirection,
+};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::timeout;
+
+const MOCK: &str = env!("CARGO_BIN_EXE_mock-app-server");
+const WAIT: Duration = Duration::from_secs(10);
+
+async fn spawn() -> AppServerBridge {
+    AppServerBridge::spawn(Path::new(MOCK), &[])
+        .await
+        .expect("mock app-server should start")
+}
+
+async fn call(bridge: &AppServerBridge, method: &str, params: Value) -> anyhow::Result<Value> {
+    bridge.request(method, params, WAIT, None).await
+}
+
+async fn received_methods(bridge: &AppServerBridge) -> Vec<String> {
+    let response = call(bridge, "mock/received", json!({})).await.unwrap();
+    response["result"]["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|message| message["method"].as_str().map(ToString::to_string))
+        .collect()
+}
+
+async fn wait_for_state(bridge: &AppServerBridge, state: AppServerState) {
+    let mut status = bridge.subscribe_status();
+    timeout(WAIT, async {
+        while bridge.status().state != state {
+            let _ = status.recv().await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("app-server never reached {state:?}"));
+}
+
+#[tokio::test]
+async fn responses_are_routed_to_their_requests() {
+    let bridge = spawn().await;
+    let slow = bridge.request(
+        "slow",
+        json!({ "mock": { "delayMs": 300 } }),
+        WAIT,
+        Some(json!("view-1")),
+    );
+    let fast = bridge.request("fast", json!({ "n": 2 }), WAIT, Some(json!(7)));
+    let (slow, fast) = tokio::join!(slow, fast);
+    let (slow, fast) = (slow.unwrap(), fast.unwrap());
+
+    assert_eq!(slow["id"], "view-1");
+    assert_eq!(slow["result"]["method"], "slow");
+    assert_eq!(fast["id"], 7);
+    assert_eq!(fast["result"]["params"]["n"], 2);
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn error_responses_are_returned_to_the_caller() {
+    let bridge = spawn().await;
+    let error = json!({ "code": -32000, "message": "nope" });
+    let response = call(&bridge, "fails", json!({ "mock": { "error": error } }))
+        .await
+        .unwrap();
+
+    assert_eq!(response["error"], error);
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn malformed_frames_fail_the_request_they_answer() {
+    let bridge = spawn().await;
+    let cases = [
+        (41, r#"{"jsonrpc":"2.0","id":41}"#, "neither a method"),
+        (42, r#"{"jsonrpc":"1.0","id":42,"result":{}}"#, "version"),
+        (
+            43,
+            r#"{"id":43,"result":{},"error":{"code":1,"message":"x"}}"#,
+            "both a result and an error",
+        ),
+    ];
+    for (id, frame, expected) in cases {
+        let err = bridge
+            .request(
+                "bad",
+                json!({ "mock": { "rawFrame": frame } }),
+                WAIT,
+                Some(json!(id)),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(expected), "{err}");
+    }
+    // Frames without the version header are accepted.
+    let response = bridge
+        .request(
+            "ok",
+            json!({ "mock": { "rawFrame": r#"{"id":44,"result":null}"# } }),
+            WAIT,
+            Some(json!(44)),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response,
+        json!({ "jsonrpc": "2.0", "id": 44, "result": null })
+    );
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn notifications_reach_subscribers() {
+    let bridge = spawn().await;
+    let mut notifications = bridge.subscribe_notifications();
+    let params = json!({ "mock": { "notifications": [
+        { "method": "thread/started", "params": { "threadId": "t1" } },
+    ] } });
+    call(&bridge, "thread/start", params).await.unwrap();
+
+    let notification = timeout(WAIT, notifications.recv()).await.unwrap().unwrap();
+    assert_eq!(notification["method"], "thread/started");
+    assert_eq!(notification["params"]["threadId"], "t1");
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn lagging_subscribers_are_told_how_much_they_missed() {
+    let bridge = spawn().await;
+    let mut everything = bridge.notification_subscription(NotificationFilter::all());
+    let mut threads =
+        bridge.notification_subscription(NotificationFilter::method_prefixes(["thread/"]));
+    let mut burst = vec![json!({ "method": "turn/progress", "params": {} }); 1999];
+    burst.push(json!({ "method": "thread/closed", "params": {} }));
+    call(
+        &bridge,
+        "burst",
+        json!({ "mock": { "notifications": burst } }),
+    )
+    .await
+    .unwrap();
+
+    let Some(NotificationEvent::Lagged { dropped }) = everything.recv().await else {
+        panic!("expected a lag report");
+    };
+    assert!(dropped > 0);
+    assert_eq!(everything.dropped(), dropped);
+    assert!(matches!(
+        everything.recv().await,
+        Some(NotificationEvent::Notification(notification)) if notification["method"] == "turn/progress"
+    ));
+
+    assert!(matches!(
+        threads.recv().await,
+        Some(NotificationEvent::Lagged { .. })
+    ));
+    assert!(matches!(
+        threads.recv().await,
+        Some(NotificationEvent::Notification(notification)) if notification["method"] == "thread/closed"
+    ));
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn requests_time_out_without_blocking_later_requests() {
+    let bridge = spawn().await;
+    let err = bridge
+        .request(
+            "hang",
+            json!({ "mock": { "noResponse": true } }),
+            Duration::from_millis(200),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("timeout"), "{err}");
+
+    let late = bridge
+        .request(
+            "late",
+            json!({ "mock": { "delayMs": 400 } }),
+            Duration::from_millis(100),
+            None,
+        )
+        .await;
+    assert!(late.is_err());
+    // The late response arrives for an id nobody waits on any more.
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(
+        call(&bridge, "next", json!({})).await.unwrap()["result"]["method"],
+        "next"
+    );
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn related_notifications_extend_the_deadline() {
+    let bridge = spawn().await;
+    let mut policy = TimeoutPolicy::default();
+    policy.methods.insert(
+        "turn/".to_string(),
+        MethodTimeout {
+            timeout_ms: Some(300),
+            extend_on_activity: true,
+        },
+    );
+    bridge.set_timeout_policy(policy);
+    let progress = json!({ "method": "turn/progress", "params": { "turnId": "u1" } });
+    let params = json!({ "turnId": "u1", "mock": {
+        "notifications": [progress, progress, progress, progress],
+        "notificationIntervalMs": 200,
+    } });
+
+    let response = bridge.call("turn/start", params, None).await.unwrap();
+    assert_eq!(response["result"]["method"], "turn/start");
+
+    let unrelated = json!({ "method": "turn/progress", "params": { "turnId": "other" } });
+    let params = json!({ "turnId": "u2", "mock": {
+        "notifications": [unrelated, unrelated, unrelated, unrelated],
+        "notificationIntervalMs": 200,
+    } });
+    assert!(bridge.call("turn/start", params, None).await.is_err());
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn cancel_fails_the_request_and_notifies_the_server() {
+    let bridge = std::sync::Arc::new(spawn().await);
+    let pending = tokio::spawn({
+        let bridge = std::sync::Arc::clone(&bridge);
+        async move {
+            bridge
+                .request(
+                    "hang",
+                    json!({ "mock": { "noResponse": true } }),
+                    WAIT,
+                    Some(json!("c1")),
+                )
+                .await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(bridge.cancel(&json!("c1")).await.unwrap());
+    let err = pending.await.unwrap().unwrap_err();
+    assert!(err.downcast_ref::<RequestCancelled>().is_some(), "{err}");
+    assert!(!bridge.cancel(&json!("c1")).await.unwrap());
+    assert!(received_methods(&bridge)
+        .await
+        .contains(&"$/cancelRequest".to_string()));
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn server_requests_are_answered_by_the_handler() {
+    let bridge = spawn().await;
+    bridge.set_request_handler(Box::new(|request| {
+        Some(Ok(
+            json!({ "approved": request.method == "execCommandApproval" }),
+        ))
+    }));
+    let params = json!({ "mock": { "serverRequest": {
+        "method": "execCommandApproval",
+        "params": { "command": "ls" },
+    } } });
+
+    let response = call(&bridge, "turn/start", params).await.unwrap();
+    assert_eq!(
+        response["result"]["reply"]["result"],
+        json!({ "approved": true })
+    );
+    assert!(bridge.outstanding_server_requests().await.is_empty());
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn crash_fails_pending_requests_and_restarts_with_handshake() {
+    let bridge = spawn().await;
+    call(
+        &bridge,
+        "initialize",
+        json!({ "clientInfo": { "name": "tests" } }),
+    )
+    .await
+    .unwrap();
+    bridge.notify("initialized", json!({})).await.unwrap();
+
+    let err = call(&bridge, "boom", json!({ "mock": { "crash": 3 } }))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("exited"), "{err}");
+
+    wait_for_state(&bridge, AppServerState::Ready).await;
+    assert_eq!(bridge.status().restarts, 1);
+    let methods = received_methods(&bridge).await;
+    assert_eq!(methods[..2], ["initialize", "initialized"]);
+    bridge.shutdown().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn output_written_after_an_exit_is_still_delivered() {
+    let bridge = spawn().await;
+    let response = call(
+        &bridge,
+        "last",
+        json!({ "mock": { "answerAfterExitMs": 200 } }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["result"]["method"], "last");
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn stderr_is_parsed_and_attached_to_crash_reports() {
+    let bridge = spawn().await;
+    let stderr = json!([
+        "2026-01-01T00:00:00.000000Z  WARN codex_core::config: falling back to defaults",
+        "\u{1b}[2m2026-01-01T00:00:00.000001Z\u{1b}[0m \u{1b}[31mERROR\u{1b}[0m codex_core: boom",
+        r#"{"level":"INFO","target":"codex_app_server","fields":{"message":"listening"}}"#,
+        "plain output",
+    ]);
+    call(&bridge, "log", json!({ "mock": { "stderr": stderr } }))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let lines = bridge.diagnostics().stderr;
+    let parsed: Vec<_> = lines
+        .iter()
+        .map(|line| (line.level, line.target.as_deref(), line.message.as_str()))
+        .collect();
+    assert_eq!(
+        parsed,
+        [
+            (
+                LogLevel::Warn,
+                Some("codex_core::config"),
+                "falling back to defaults"
+            ),
+            (LogLevel::Error, Some("codex_core"), "boom"),
+            (LogLevel::Info, Some("codex_app_server"), "listening"),
+            (LogLevel::Unknown, None, "plain output"),
+        ]
+    );
+
+    let pid = bridge.status().pid;
+    let _ = call(&bridge, "boom", json!({ "mock": { "crash": 3 } })).await;
+    wait_for_state(&bridge, AppServerState::Ready).await;
+    let diagnostics = bridge.diagnostics();
+    let [crash] = diagnostics.crashes.as_slice() else {
+        panic!("expected one crash report: {:?}", diagnostics.crashes);
+    };
+    assert_eq!(crash.exit_code, Some(3));
+    assert_eq!(crash.pid, pid);
+    assert_eq!(crash.restarts, 0);
+    assert_eq!(crash.stderr_tail.len(), 4);
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn recorded_traces_replay_against_live_request_ids() {
+    let trace = std::env::temp_dir().join(format!(
+        "app-server-bridge-trace-{}.jsonl",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&trace);
+    let bridge = spawn().await;
+    bridge.start_trace(&trace).unwrap();
+    let params = json!({ "mock": { "notifications": [
+        { "method": "thread/started", "params": { "threadId": "t1" } },
+    ] } });
+    call(&bridge, "thread/start", params).await.unwrap();
+    call(&bridge, "turn/start", json!({ "text": "hi" }))
+        .await
+        .unwrap();
+    assert_eq!(bridge.stop_trace().as_deref(), Some(trace.as_path()));
+    bridge.shutdown().await.unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&trace).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    let directions = app_server_bridge::read_trace(&trace)
+        .unwrap()
+        .iter()
+        .map(|record| record.direction)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        directions,
+        [
+            TraceDirection::ToServer,
+            TraceDirection::FromServer,
+            TraceDirection::FromServer,
+            TraceDirection::ToServer,
+            TraceDirection::FromServer,
+        ]
+    );
+
+    let replay = AppServerBridge::replay(&trace).await.unwrap();
+    let mut notifications = replay.subscribe_notifications();
+    let response = replay
+        .request("thread/start", json!({}), WAIT, Some(json!("live-1")))
+        .await
+        .unwrap();
+    assert_eq!(response["id"], "live-1");
+    assert_eq!(response["result"]["method"], "thread/start");
+    let notification = timeout(WAIT, notifications.recv()).await.unwrap().unwrap();
+    assert_eq!(notification["params"]["threadId"], "t1");
+    let response = call(&replay, "turn/start", json!({})).await.unwrap();
+    assert_eq!(response["result"]["params"]["text"], "hi");
+    let response = call(&replay, "turn/interrupt", json!({})).await.unwrap();
+    assert_eq!(response["error"]["code"], -32601);
+
+    replay.shutdown().await.unwrap();
+    let _ = std::fs::remove_file(&trace);
+}
+
+#[tokio::test]
+async fn shutdown_stops_the_process_and_fails_pending_requests() {
+    let bridge = std::sync::Arc::new(spawn().await);
+    let pending = tokio::spawn({
+        let bridge = std::sync::Arc::clone(&bridge);
+        async move { call(&bridge, "hang", json!({ "mock": { "noResponse": true } })).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    bridge.shutdown().await.unwrap();
+    assert_eq!(bridge.status().state, AppServerState::Stopped);
+    let err = pending.await.unwrap().unwrap_err();
+    assert!(err.to_string().contains("shut down"), "{err}");
+    assert!(call(&bridge, "after", json!({})).await.is_err());
+    // Shutting down twice is harmless.
+    bridge.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_lets_in_flight_requests_finish() {
+    let bridge = std::sync::Arc::new(spawn().await);
+    let in_flight = tokio::spawn({
+        let bridge = std::sync::Arc::clone(&bridge);
+        async move { call(&bridge, "turn/start", json!({ "mock": { "delayMs": 300 } })).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    bridge.shutdown().await.unwrap();
+    let response = in_flight.await.unwrap().unwrap();
+    assert_eq!(response["result"]["method"], "turn/start");
+    assert_eq!(bridge.status().state, AppServerState::Stopped);
+    assert!(bridge.diagnostics().crashes.is_empty());
+}
+
+#[tokio::test]
+async fn shutdown_terminates_a_server_that_ignores_closed_input() {
+    let bridge = spawn().await;
+    bridge.set_shutdown_policy(ShutdownPolicy {
+        exit_timeout_ms: 200,
+        ..ShutdownPolicy::default()
+    });
+    call(
+        &bridge,
+        "stubborn",
+        json!({ "mock": { "ignoreEof": true } }),
+    )
+    .await
+    .unwrap();
+
+    let started = std::time::Instant::now();
+    bridge.shutdown().await.unwrap();
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        started.elapsed()
+    );
+    assert_eq!(bridge.status().state, AppServerState::Stopped);
+}
@@ -0,0 +1,153 @@
+use app_server_bridge::{AppServerBridge, AppServerState, AppServerTransport};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+
+const MOCK: &str = env!("CARGO_BIN_EXE_mock-app-server");
+const WAIT: Duration = Duration::from_secs(10);
+
+fn spawn_mock() -> Child {
+    Command::new(MOCK)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("mock app-server should start")
+}
+
+/// Serves one mock app-server per accepted connection, until it exits.
+async fn serve_stream<S: AsyncRead + AsyncWrite>(stream: S) {
+    let mut mock = spawn_mock();
+    let mut stdin = mock.stdin.take().unwrap();
+    let mut stdout = mock.stdout.take().unwrap();
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    tokio::select! {
+        _ = tokio::io::copy(&mut reader, &mut stdin) => {}
+        _ = tokio::io::copy(&mut stdout, &mut writer) => {}
+    }
+}
+
+async fn wait_for_restart(bridge: &AppServerBridge) {
+    let mut status = bridge.subscribe_status();
+    timeout(WAIT, async {
+        while bridge.status().state != AppServerState::Ready || bridge.status().restarts == 0 {
+            let _ = status.recv().await;
+        }
+    })
+    .await
+    .expect("bridge should reconnect");
+}
+
+async fn received_methods(bridge: &AppServerBridge) -> Vec<String> {
+    let response = bridge
+        .request("mock/received", json!({}), WAIT, None)
+        .await
+        .unwrap();
+    response["result"]["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|message| message["method"].as_str().map(ToString::to_string))
+        .collect()
+}
+
+#[test]
+fn urls_select_a_transport() {
+    assert!(matches!(
+        AppServerTransport::parse_url("ws://127.0.0.1:4500"),
+        Ok(AppServerTransport::WebSocket(_))
+    ));
+    #[cfg(unix)]
+    assert!(matches!(
+        AppServerTransport::parse_url("unix:///tmp/codex.sock"),
+        Ok(AppServerTransport::UnixSocket(path)) if path.to_str() == Some("/tmp/codex.sock")
+    ));
+    assert!(AppServerTransport::parse_url("wss://example.com").is_err());
+    assert!(AppServerTransport::parse_url("http://localhost").is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_socket_transport_reconnects_after_the_server_drops() {
+    let path = std::env::temp_dir().join(format!("app-server-bridge-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve_stream(stream));
+        }
+    });
+
+    let bridge = AppServerBridge::connect(AppServerTransport::UnixSocket(path.clone()))
+        .await
+        .unwrap();
+    let response = bridge
+        .request("initialize", json!({ "client": "tests" }), WAIT, None)
+        .await
+        .unwrap();
+    assert_eq!(response["result"]["method"], "initialize");
+    assert_eq!(bridge.status().pid, None);
+
+    let err = bridge
+        .request("boom", json!({ "mock": { "crash": 1 } }), WAIT, None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("connection closed"), "{err}");
+    wait_for_restart(&bridge).await;
+    assert_eq!(received_methods(&bridge).await[0], "initialize");
+
+    bridge.shutdown().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn websocket_transport_carries_one_frame_per_message() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let (mut sink, mut messages) = socket.split();
+                let mut mock = spawn_mock();
+                let mut stdin = mock.stdin.take().unwrap();
+                let mut stdout = BufReader::new(mock.stdout.take().unwrap()).lines();
+                loop {
+                    tokio::select! {
+                        Ok(Some(line)) = stdout.next_line() => {
+                            sink.send(Message::text(line)).await.unwrap();
+                        }
+                        Some(Ok(message)) = messages.next() => {
+                            let Message::Text(text) = message else { break };
+                            stdin.write_all(format!("{text}\n").as_bytes()).await.unwrap();
+                        }
+                        else => break,
+                    }
+                }
+            });
+        }
+    });
+
+    let transport = AppServerTransport::parse_url(&url).unwrap();
+    let bridge = AppServerBridge::connect(transport).await.unwrap();
+    let mut notifications = bridge.subscribe_notifications();
+    let params = json!({ "mock": { "notifications": [
+        { "method": "thread/started", "params": { "threadId": "t1" } },
+    ] } });
+    let response: Value = bridge
+        .request("thread/start", params, WAIT, Some(json!("ws-1")))
+        .await
+        .unwrap();
+    assert_eq!(response["id"], "ws-1");
+    assert_eq!(response["result"]["method"], "thread/start");
+    let notification = timeout(WAIT, notifications.recv()).await.unwrap().unwrap();
+    assert_eq!(notification["params"]["threadId"], "t1");
+
+    bridge.shutdown().await.unwrap();
+    assert_eq!(bridge.status().state, AppServerState::Stopped);
+}
@@ -0,0 +1,404 @@
+use crate::AppServerEnvelope;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use thiserror::Error;
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// A request id; JSON-RPC allows strings and integers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(id) => write!(f, "{id}"),
+            Self::String(id) => f.write_str(id),
+        }
+    }
+}
+
+impl TryFrom<&Value> for RequestId {
+    type Error = JsonRpcFrameError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(id) => Ok(Self::String(id.clone())),
+            Value::Number(id) => id
+                .as_i64()
+                .map(Self::Number)
+                .ok_or_else(|| JsonRpcFrameError::InvalidId(value.clone())),
+            _ => Err(JsonRpcFrameError::InvalidId(value.clone())),
+        }
+    }
+}
+
+impl From<RequestId> for Value {
+    fn from(id: RequestId) -> Self {
+        match id {
+            RequestId::Number(id) => Value::from(id),
+            RequestId::String(id) => Value::String(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// Answer to a request the client cancelled with `$/cancelRequest`.
+    pub const REQUEST_CANCELLED: i64 = -32800;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcRequest {
+    pub id: RequestId,
+    pub method: String,
+    pub params: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcNotification {
+    pub method: String,
+    pub params: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcResponse {
+    pub id: RequestId,
+    pub result: Value,
+}
+
+/// An error response. `id` is `None` when the peer could not tell which
+/// request failed, e.g. for a frame it could not parse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcErrorResponse {
+    pub id: Option<RequestId>,
+    pub error: JsonRpcError,
+}
+
+/// One newline-delimited JSON-RPC 2.0 frame.
+///
+/// `jsonrpc: "2.0"` is always written but optional when parsing, since
+/// `codex app-server` leaves it out; any other version is rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonRpcMessage {
+    Request(JsonRpcRequest),
+    Notification(JsonRpcNotification),
+    Response(JsonRpcResponse),
+    Error(JsonRpcErrorResponse),
+}
+
+/// Why a frame is not a valid JSON-RPC message.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum JsonRpcFrameError {
+    #[error("frame is not valid json: {0}")]
+    Parse(String),
+    #[error("malformed json-rpc frame: {0}")]
+    Malformed(String),
+    #[error("unsupported json-rpc version {0:?}")]
+    UnsupportedVersion(String),
+    #[error("invalid json-rpc id {0}, expected a string or an integer")]
+    InvalidId(Value),
+    #[error("json-rpc method must not be empty")]
+    EmptyMethod,
+    #[error("json-rpc frame has both a method and a result or error")]
+    MethodWithResult,
+    #[error("json-rpc response has both a result and an error")]
+    ResultAndError,
+    #[error("json-rpc response is missing its id")]
+    MissingId,
+    #[error("json-rpc frame has neither a method nor a result or error")]
+    Unclassified,
+    #[error("invalid json-rpc error object: {0}")]
+    InvalidError(String),
+}
+
+impl JsonRpcFrameError {
+    /// The standard error code to answer the frame with.
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::Parse(_) => JsonRpcError::PARSE_ERROR,
+            _ => JsonRpcError::INVALID_REQUEST,
+        }
+    }
+}
+
+impl JsonRpcMessage {
+    pub fn parse_line(line: &str) -> Result<Self, JsonRpcFrameError> {
+        let value =
+            serde_json::from_str(line).map_err(|err| JsonRpcFrameError::Parse(err.to_string()))?;
+        Self::parse(value)
+    }
+
+    pub fn parse(value: Value) -> Result<Self, JsonRpcFrameError> {
+        let envelope: AppServerEnvelope = serde_json::from_value(value)
+            .map_err(|err| JsonRpcFrameError::Malformed(err.to_string()))?;
+        Self::try_from(envelope)
+    }
+
+    pub fn request(id: RequestId, method: impl Into<String>, params: Value) -> Self {
+        Self::Request(JsonRpcRequest {
+            id,
+            method: method.into(),
+            params: Some(params),
+        })
+    }
+
+    pub fn notification(method: impl Into<String>, params: Value) -> Self {
+        Self::Notification(JsonRpcNotification {
+            method: method.into(),
+            params: Some(params),
+        })
+    }
+
+    /// A response carrying either `result` or `error`.
+    pub fn reply(id: RequestId, reply: Result<Value, JsonRpcError>) -> Self {
+        match reply {
+            Ok(result) => Self::Response(JsonRpcResponse { id, result }),
+            Err(error) => Self::Error(JsonRpcErrorResponse {
+                id: Some(id),
+                error,
+            }),
+        }
+    }
+
+    pub fn method(&self) -> Option<&str> {
+        match self {
+            Self::Request(request) => Some(&request.method),
+            Self::Notification(notification) => Some(&notification.method),
+            Self::Response(_) | Self::Error(_) => None,
+        }
+    }
+
+    pub fn id(&self) -> Option<&RequestId> {
+        match self {
+            Self::Request(request) => Some(&request.id),
+            Self::Response(response) => Some(&response.id),
+            Self::Error(response) => response.id.as_ref(),
+            Self::Notification(_) => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut frame = Map::new();
+        frame.insert("jsonrpc".to_string(), json!(JSONRPC_VERSION));
+        let mut insert = |key: &str, value: Value| {
+            frame.insert(key.to_string(), value);
+        };
+        match self {
+            Self::Request(request) => {
+                insert("id", request.id.clone().into());
+                insert("method", json!(request.method));
+                if let Some(params) = &request.params {
+                    insert("params", params.clone());
+                }
+            }
+            Self::Notification(notification) => {
+                insert("method", json!(notification.method));
+                if let Some(params) = &notification.params {
+                    insert("params", params.clone());
+                }
+            }
+            Self::Response(response) => {
+                insert("id", response.id.clone().into());
+                insert("result", response.result.clone());
+            }
+            Self::Error(response) => {
+                insert(
+                    "id",
+                    response.id.clone().map(Value::from).unwrap_or_default(),
+                );
+                insert("error", json!(response.error));
+            }
+        }
+        Value::Object(frame)
+    }
+}
+
+impl TryFrom<AppServerEnvelope> for JsonRpcMessage {
+    type Error = JsonRpcFrameError;
+
+    fn try_from(envelope: AppServerEnvelope) -> Result<Self, JsonRpcFrameError> {
+        if let Some(version) = envelope.jsonrpc {
+            if version != JSONRPC_VERSION {
+                return Err(JsonRpcFrameError::UnsupportedVersion(version));
+            }
+        }
+        let id = envelope.id.as_ref().map(RequestId::try_from).transpose()?;
+        if let Some(method) = envelope.method {
+            if envelope.result.is_some() || envelope.error.is_some() {
+                return Err(JsonRpcFrameError::MethodWithResult);
+            }
+            if method.is_empty() {
+                return Err(JsonRpcFrameError::EmptyMethod);
+            }
+            let params = envelope.params;
+            return Ok(match id {
+                Some(id) => Self::Request(JsonRpcRequest { id, method, params }),
+                None => Self::Notification(JsonRpcNotification { method, params }),
+            });
+        }
+        match (envelope.result, envelope.error) {
+            (Some(_), Some(_)) => Err(JsonRpcFrameError::ResultAndError),
+            (Some(result), None) => Ok(Self::Response(JsonRpcResponse {
+                id: id.ok_or(JsonRpcFrameError::MissingId)?,
+                result,
+            })),
+            (None, Some(error)) => Ok(Self::Error(JsonRpcErrorResponse {
+                id,
+                error: serde_json::from_value(error)
+                    .map_err(|err| JsonRpcFrameError::InvalidError(err.to_string()))?,
+            })),
+            (None, None) => Err(JsonRpcFrameError::Unclassified),
+        }
+    }
+}
+
+impl Serialize for JsonRpcMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonRpcMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Self::parse(value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: Value) -> Result<JsonRpcMessage, JsonRpcFrameError> {
+        JsonRpcMessage::parse(value)
+    }
+
+    #[test]
+    fn messages_round_trip_through_values() {
+        let messages = [
+            JsonRpcMessage::request(RequestId::Number(1), "thread/start", json!({ "a": 1 })),
+            JsonRpcMessage::notification("turn/started", json!({})),
+            JsonRpcMessage::reply(
+                RequestId::String("x".to_string()),
+                Ok(json!({ "ok": true })),
+            ),
+            JsonRpcMessage::reply(RequestId::Number(2), Ok(Value::Null)),
+            JsonRpcMessage::reply(
+                RequestId::Number(3),
+                Err(JsonRpcError::new(JsonRpcError::METHOD_NOT_FOUND, "nope")),
+            ),
+        ];
+        for message in messages {
+            let value = message.to_value();
+            assert_eq!(value["jsonrpc"], JSONRPC_VERSION);
+            assert_eq!(parse(value).unwrap(), message);
+            let line = serde_json::to_string(&message).unwrap();
+            assert_eq!(JsonRpcMessage::parse_line(&line).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn null_results_are_responses() {
+        let message = parse(json!({ "id": 7, "result": null })).unwrap();
+        assert_eq!(
+            message,
+            JsonRpcMessage::Response(JsonRpcResponse {
+                id: RequestId::Number(7),
+                result: Value::Null,
+            })
+        );
+        assert_eq!(message.to_value()["result"], Value::Null);
+        assert!(message
+            .to_value()
+            .as_object()
+            .unwrap()
+            .contains_key("result"));
+    }
+
+    #[test]
+    fn the_version_is_optional_but_must_be_2_0() {
+        assert!(parse(json!({ "method": "initialized" })).is_ok());
+        assert_eq!(
+            parse(json!({ "jsonrpc": "1.0", "method": "initialized" })),
+            Err(JsonRpcFrameError::UnsupportedVersion("1.0".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_frames_are_rejected() {
+        let rejected = |value: Value| parse(value).unwrap_err();
+        assert_eq!(
+            rejected(json!({ "id": 1, "method": "a", "result": {} })),
+            JsonRpcFrameError::MethodWithResult
+        );
+        assert_eq!(
+            rejected(json!({ "id": 1, "result": {}, "error": { "code": 1, "message": "" } })),
+            JsonRpcFrameError::ResultAndError
+        );
+        assert_eq!(
+            rejected(json!({ "result": {} })),
+            JsonRpcFrameError::MissingId
+        );
+        assert_eq!(
+            rejected(json!({ "id": 1 })),
+            JsonRpcFrameError::Unclassified
+        );
+        assert_eq!(
+            rejected(json!({ "id": 1, "method": "" })),
+            JsonRpcFrameError::EmptyMethod
+        );
+        assert!(matches!(
+            rejected(json!({ "id": 1, "error": { "message": "no code" } })),
+            JsonRpcFrameError::InvalidError(_)
+        ));
+        for id in [json!(1.5), json!(true), json!({}), json!([1])] {
+            assert_eq!(
+                rejected(json!({ "id": id, "result": {} })),
+                JsonRpcFrameError::InvalidId(id)
+            );
+        }
+        let parse_error = JsonRpcMessage::parse_line("{").unwrap_err();
+        assert_eq!(parse_error.code(), JsonRpcError::PARSE_ERROR);
+        assert!(matches!(
+            parse(json!([])).unwrap_err(),
+            JsonRpcFrameError::Malformed(_)
+        ));
+    }
+
+    #[test]
+    fn error_responses_may_lack_an_id() {
+        let message = parse(json!({
+            "id": null,
+            "error": { "code": JsonRpcError::PARSE_ERROR, "message": "bad" },
+        }))
+        .unwrap();
+        assert_eq!(message.id(), None);
+        assert_eq!(message.to_value()["id"], Value::Null);
+    }
+}
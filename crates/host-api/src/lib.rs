@@ -1,4 +1,11 @@
-use serde::{Deserialize, Serialize};
+mod jsonrpc;
+
+pub use jsonrpc::{
+    JsonRpcError, JsonRpcErrorResponse, JsonRpcFrameError, JsonRpcMessage, JsonRpcNotification,
+    JsonRpcRequest, JsonRpcResponse, RequestId, JSONRPC_VERSION,
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 
@@ -100,29 +107,128 @@ pub struct WorkerEvent {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TerminalSession {
     pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub created_at_ms: u64,
     pub cwd: String,
     #[serde(default)]
     pub env: std::collections::BTreeMap<String, String>,
     pub cols: u16,
     pub rows: u16,
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub login: bool,
+    #[serde(default)]
+    pub interactive: bool,
+    #[serde(default)]
+    pub close_on_exit: bool,
+    #[serde(default)]
+    pub shell_integration: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrollback_lines: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrollback_bytes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<TerminalExitStatus>,
+}
+
+/// A named terminal setup stored in state and selectable from
+/// `terminal-create` through `profileId`. Unset fields fall back to the
+/// request parameters and then to the built-in defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalProfile {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: std::collections::BTreeMap<String, String>,
+    /// Working directory template. `~` and `${VAR}` are expanded, where
+    /// `${cwd}` is the `cwd` passed to `terminal-create`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cols: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrollback_lines: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrollback_bytes: Option<usize>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalExitStatus {
+    pub exit_code: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+    pub success: bool,
+}
+
+/// A command delimited by shell integration markers. Offsets are absolute
+/// byte offsets into the session's output stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalCommand {
+    pub index: u64,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    pub started_at_ms: u64,
+    #[serde(default)]
+    pub finished_at_ms: Option<u64>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub prompt_offset: Option<u64>,
+    pub output_start: u64,
+    #[serde(default)]
+    pub output_end: Option<u64>,
+}
+
+/// Raw app-server frame; see [`JsonRpcMessage`] for the validated form.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppServerEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jsonrpc: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub method: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub params: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// `Some(Value::Null)` for `"result": null`, which is a valid result.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<Value>,
 }
 
+fn deserialize_present<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum WindowType {
@@ -182,6 +288,8 @@ pub fn parse_deep_link(raw: &str) -> Result<DeepLinkRoute, DeepLinkParseError> {
 pub const QUERY_METHODS: &[&str] = &[
     "account-info",
     "active-workspace-roots",
+    "app-server-diagnostics",
+    "app-server-status",
     "child-processes",
     "codex-home",
     "extension-info",
@@ -209,12 +317,23 @@ pub const QUERY_METHODS: &[&str] = &[
     "read-file-binary",
     "read-git-file-binary",
     "recommended-skills",
+    "terminal-commands",
+    "terminal-history",
+    "terminal-history-output",
+    "terminal-list",
+    "terminal-profiles",
+    "terminal-search",
+    "terminal-snapshot",
+    "terminal-stats",
     "third-party-notices",
     "workspace-root-options",
 ];
 
 pub const MUTATION_METHODS: &[&str] = &[
     "add-workspace-root-option",
+    "app-server-cancel-request",
+    "app-server-trace-start",
+    "app-server-trace-stop",
     "apply-patch",
     "automation-create",
     "automation-delete",
@@ -239,6 +358,15 @@ pub const MUTATION_METHODS: &[&str] = &[
     "terminal-write",
     "terminal-resize",
     "terminal-close",
+    "terminal-restart",
+    "terminal-history-delete",
+    "terminal-recording-start",
+    "terminal-recording-stop",
+    "terminal-recording-export",
+    "terminal-set-label",
+    "terminal-signal",
+    "terminal-profile-save",
+    "terminal-profile-delete",
 ];
 
 pub const GIT_WORKER_METHODS: &[&str] = &[
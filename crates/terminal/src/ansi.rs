@@ -0,0 +1,112 @@
+/// Renders raw terminal bytes as plain text: CSI/OSC/other escape sequences
+/// are dropped, backspaces erase the previous character and carriage returns
+/// are ignored.
+pub(crate) fn strip_ansi(bytes: &[u8]) -> String {
+    strip_ansi_mapped(bytes, 0).0
+}
+
+/// Like [`strip_ansi`], additionally returning for each byte of the text the
+/// absolute offset (`base` + index) of the raw byte it was decoded from.
+pub(crate) fn strip_ansi_mapped(bytes: &[u8], base: u64) -> (String, Vec<u64>) {
+    let mut out = String::with_capacity(bytes.len());
+    let mut map = Vec::with_capacity(bytes.len());
+    let mut chars = decode_lossy(bytes).into_iter().peekable();
+    while let Some((index, ch, raw_len)) = chars.next() {
+        match ch {
+            '\u{1b}' => match chars.next().map(|(_, next, _)| next) {
+                Some('[') => {
+                    // CSI: parameters and intermediates up to a final byte in @..~
+                    for (_, next, _) in chars.by_ref() {
+                        if ('@'..='~').contains(&next) {
+                            break;
+                        }
+                    }
+                }
+                Some(']') | Some('P') | Some('_') | Some('^') => {
+                    // OSC/DCS/APC/PM: terminated by BEL or ST (ESC \)
+                    while let Some((_, next, _)) = chars.next() {
+                        if next == '\u{7}' {
+                            break;
+                        }
+                        if next == '\u{1b}' && chars.peek().map(|(_, ch, _)| *ch) == Some('\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                Some('(') | Some(')') | Some('*') | Some('+') => {
+                    chars.next();
+                }
+                _ => {}
+            },
+            '\u{8}' => {
+                if let Some(erased) = out.pop() {
+                    map.truncate(map.len() - erased.len_utf8());
+                }
+            }
+            '\r' | '\u{7}' => {}
+            _ => {
+                let offset = base + index as u64;
+                out.push(ch);
+                // Valid characters map byte for byte; a replacement character
+                // maps every byte to the start of the invalid sequence.
+                let exact = raw_len == ch.len_utf8();
+                map.extend(
+                    (0..ch.len_utf8() as u64).map(|i| if exact { offset + i } else { offset }),
+                );
+            }
+        }
+    }
+    (out, map)
+}
+
+/// Decodes `bytes` like `String::from_utf8_lossy`, keeping the byte index at
+/// which each character starts and how many raw bytes it consumed.
+fn decode_lossy(bytes: &[u8]) -> Vec<(usize, char, usize)> {
+    let mut chars = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    for chunk in bytes.utf8_chunks() {
+        chars.extend(
+            chunk
+                .valid()
+                .char_indices()
+                .map(|(offset, ch)| (index + offset, ch, ch.len_utf8())),
+        );
+        index += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            chars.push((index, char::REPLACEMENT_CHARACTER, chunk.invalid().len()));
+            index += chunk.invalid().len();
+        }
+    }
+    chars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_escape_sequences_and_applies_backspaces() {
+        assert_eq!(
+            strip_ansi(b"\x1b[1;31mred\x1b[0m \x1b]0;title\x07ok\x1b]7;x\x1b\\\r\n"),
+            "red ok\n"
+        );
+        assert_eq!(strip_ansi(b"abx\x08c\x07"), "abc");
+        assert_eq!(strip_ansi(b"\x1b(Bplain"), "plain");
+    }
+
+    #[test]
+    fn maps_text_back_to_raw_offsets() {
+        let (text, map) = strip_ansi_mapped("\x1b[1mé\x1b[0mx".as_bytes(), 100);
+        assert_eq!(text, "éx");
+        assert_eq!(map, [104, 105, 110]);
+
+        let (text, map) = strip_ansi_mapped(b"a\xffb", 0);
+        assert_eq!(text, "a\u{fffd}b");
+        assert_eq!(map, [0, 1, 1, 1, 2]);
+
+        let (text, map) = strip_ansi_mapped("aé\x08c".as_bytes(), 0);
+        assert_eq!(text, "ac");
+        assert_eq!(map, [0, 4]);
+    }
+}
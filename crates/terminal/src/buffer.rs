@@ -0,0 +1,324 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+pub(crate) const DEFAULT_SCROLLBACK_BYTES: usize = 1_048_576;
+pub(crate) const DEFAULT_MEMORY_BUDGET_BYTES: usize = 64 * 1_048_576;
+const MIN_SCROLLBACK_BYTES: usize = 4096;
+/// Output a session keeps even while the manager is over its memory budget.
+const MIN_RETAINED_BYTES: usize = 64 * 1024;
+
+/// Memory held by all sessions of one manager, against a soft limit: output
+/// buffers plus screen models and recordings (see [`MemoryCharge`]). Only
+/// output buffers give way when it is exceeded.
+pub(crate) struct MemoryBudget {
+    limit: AtomicUsize,
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit: AtomicUsize::new(limit),
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub(crate) fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn acquire(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    fn excess(&self) -> usize {
+        self.used().saturating_sub(self.limit())
+    }
+}
+
+/// Memory other than an output buffer that a session holds against the
+/// budget, re-measured by its owner as it changes.
+pub(crate) struct MemoryCharge {
+    budget: Arc<MemoryBudget>,
+    bytes: usize,
+}
+
+impl MemoryCharge {
+    pub(crate) fn new(budget: Arc<MemoryBudget>) -> Self {
+        Self { budget, bytes: 0 }
+    }
+
+    pub(crate) fn set(&mut self, bytes: usize) {
+        if bytes > self.bytes {
+            self.budget.acquire(bytes - self.bytes);
+        } else {
+            self.budget.release(self.bytes - bytes);
+        }
+        self.bytes = bytes;
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// Ring buffer of the most recent output of a session, addressed by absolute
+/// stream offsets. The oldest bytes are evicted once the buffer reaches its
+/// capacity, or while the manager is over its memory budget; in the latter
+/// case the session producing output gives way, down to `MIN_RETAINED_BYTES`.
+pub(crate) struct OutputBuffer {
+    data: VecDeque<u8>,
+    start_offset: u64,
+    capacity: usize,
+    budget: Arc<MemoryBudget>,
+    budget_dropped: u64,
+}
+
+impl OutputBuffer {
+    pub(crate) fn new(capacity: Option<usize>, budget: Arc<MemoryBudget>) -> Self {
+        Self {
+            data: VecDeque::new(),
+            start_offset: 0,
+            capacity: capacity
+                .unwrap_or(DEFAULT_SCROLLBACK_BYTES)
+                .max(MIN_SCROLLBACK_BYTES),
+            budget,
+            budget_dropped: 0,
+        }
+    }
+
+    pub(crate) fn start_offset(&self) -> u64 {
+        self.start_offset
+    }
+
+    pub(crate) fn end_offset(&self) -> u64 {
+        self.start_offset + self.data.len() as u64
+    }
+
+    /// Returns the retained bytes at or after `offset` together with the
+    /// absolute offset of the first returned byte.
+    pub(crate) fn since(&self, offset: u64) -> (u64, Cow<'_, [u8]>) {
+        let from = offset.clamp(self.start_offset, self.end_offset());
+        let start = (from - self.start_offset) as usize;
+        (from, self.slice(start, self.data.len()))
+    }
+
+    /// Returns `[from, to)` if that range is still retained.
+    pub(crate) fn range(&self, from: u64, to: u64) -> Option<Cow<'_, [u8]>> {
+        if from < self.start_offset || to > self.end_offset() || from > to {
+            return None;
+        }
+        let start = (from - self.start_offset) as usize;
+        let end = (to - self.start_offset) as usize;
+        Some(self.slice(start, end))
+    }
+
+    /// Borrows when the range does not wrap around the ring.
+    fn slice(&self, start: usize, end: usize) -> Cow<'_, [u8]> {
+        let (head, tail) = self.data.as_slices();
+        if end <= head.len() {
+            Cow::Borrowed(&head[start..end])
+        } else if start >= head.len() {
+            Cow::Borrowed(&tail[start - head.len()..end - head.len()])
+        } else {
+            let mut joined = Vec::with_capacity(end - start);
+            joined.extend_from_slice(&head[start..]);
+            joined.extend_from_slice(&tail[..end - head.len()]);
+            Cow::Owned(joined)
+        }
+    }
+
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        // Bytes that would be evicted right away are never stored.
+        let skip = bytes.len().saturating_sub(self.capacity);
+        let bytes = &bytes[skip..];
+        self.start_offset += skip as u64;
+        let overflow = (self.data.len() + bytes.len()).saturating_sub(self.capacity);
+        let mut evicted = skip > 0 || overflow > 0;
+        self.evict(overflow);
+        self.data.extend(bytes);
+        self.budget.acquire(bytes.len());
+        let over_budget = self
+            .budget
+            .excess()
+            .min(self.data.len().saturating_sub(MIN_RETAINED_BYTES));
+        if over_budget > 0 {
+            self.evict(over_budget);
+            self.budget_dropped += over_budget as u64;
+            evicted = true;
+        }
+        if evicted {
+            // Never leave the retained output starting inside a UTF-8 sequence.
+            self.evict(leading_continuation_bytes(&self.data));
+        }
+    }
+
+    fn evict(&mut self, count: usize) {
+        let count = count.min(self.data.len());
+        self.data.drain(..count);
+        self.start_offset += count as u64;
+        self.budget.release(count);
+    }
+
+    pub(crate) fn stats(&self) -> serde_json::Value {
+        serde_json::json!({
+            "capacityBytes": self.capacity,
+            "heldBytes": self.data.len(),
+            "totalBytes": self.end_offset(),
+            "droppedBytes": self.start_offset,
+            "budgetDroppedBytes": self.budget_dropped,
+        })
+    }
+}
+
+impl Drop for OutputBuffer {
+    fn drop(&mut self) {
+        self.budget.release(self.data.len());
+    }
+}
+
+/// Number of UTF-8 continuation bytes at the start of `bytes` (at most three,
+/// the longest tail a multi-byte sequence can leave behind).
+pub(crate) fn leading_continuation_bytes<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> usize {
+    bytes
+        .into_iter()
+        .take(3)
+        .take_while(|&&byte| byte & 0xC0 == 0x80)
+        .count()
+}
+
+/// Length of `bytes` without a trailing multi-byte sequence that is still
+/// incomplete. Invalid bytes are kept; they decode as replacement characters.
+pub fn complete_utf8_len(bytes: &[u8]) -> usize {
+    let tail_start = bytes.len().saturating_sub(3);
+    for index in (tail_start..bytes.len()).rev() {
+        let byte = bytes[index];
+        if byte & 0xC0 == 0x80 {
+            continue;
+        }
+        let needed = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return bytes.len(),
+        };
+        return if bytes.len() - index < needed {
+            index
+        } else {
+            bytes.len()
+        };
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(capacity: usize, budget: usize) -> OutputBuffer {
+        OutputBuffer::new(Some(capacity), Arc::new(MemoryBudget::new(budget)))
+    }
+
+    #[test]
+    fn evicts_oldest_bytes_beyond_capacity() {
+        let mut buffer = buffer(MIN_SCROLLBACK_BYTES, usize::MAX);
+        buffer.push(&[b'a'; MIN_SCROLLBACK_BYTES]);
+        buffer.push(b"bc");
+        assert_eq!(buffer.start_offset(), 2);
+        assert_eq!(buffer.end_offset(), MIN_SCROLLBACK_BYTES as u64 + 2);
+        let (offset, tail) = buffer.since(0);
+        assert_eq!(offset, 2);
+        assert_eq!(tail.len(), MIN_SCROLLBACK_BYTES);
+        assert!(tail.ends_with(b"abc"));
+        assert_eq!(buffer.range(1, 3), None);
+        assert_eq!(
+            buffer
+                .range(buffer.end_offset() - 2, buffer.end_offset())
+                .as_deref(),
+            Some(&b"bc"[..])
+        );
+    }
+
+    #[test]
+    fn eviction_never_starts_inside_a_character() {
+        let mut buffer = buffer(MIN_SCROLLBACK_BYTES, usize::MAX);
+        buffer.push("é".repeat(MIN_SCROLLBACK_BYTES / 2).as_bytes());
+        buffer.push(b"a");
+        // One byte had to go; the rest of its character went with it.
+        assert_eq!(buffer.start_offset(), 2);
+        let (_, retained) = buffer.since(0);
+        assert!(std::str::from_utf8(&retained).is_ok());
+    }
+
+    #[test]
+    fn sessions_over_the_budget_give_way_down_to_a_minimum() {
+        let budget = Arc::new(MemoryBudget::new(MIN_RETAINED_BYTES + 1024));
+        let mut quiet = OutputBuffer::new(None, Arc::clone(&budget));
+        let mut noisy = OutputBuffer::new(None, Arc::clone(&budget));
+        quiet.push(&[b'q'; 1024]);
+        noisy.push(&[b'n'; 2 * MIN_RETAINED_BYTES]);
+        assert_eq!(quiet.since(0).1.len(), 1024);
+        assert_eq!(noisy.since(0).1.len(), MIN_RETAINED_BYTES);
+        assert_eq!(
+            noisy.stats()["budgetDroppedBytes"],
+            MIN_RETAINED_BYTES as u64
+        );
+        assert_eq!(budget.used(), MIN_RETAINED_BYTES + 1024);
+        drop(noisy);
+        assert_eq!(budget.used(), 1024);
+    }
+
+    #[test]
+    fn charged_memory_counts_against_the_budget() {
+        let budget = Arc::new(MemoryBudget::new(2 * MIN_RETAINED_BYTES));
+        let mut screen = MemoryCharge::new(Arc::clone(&budget));
+        screen.set(2 * MIN_RETAINED_BYTES);
+        let mut output = OutputBuffer::new(None, Arc::clone(&budget));
+        output.push(&[b'o'; 2 * MIN_RETAINED_BYTES]);
+        assert_eq!(output.since(0).1.len(), MIN_RETAINED_BYTES);
+        screen.set(1024);
+        assert_eq!(budget.used(), MIN_RETAINED_BYTES + 1024);
+        drop(screen);
+        assert_eq!(budget.used(), MIN_RETAINED_BYTES);
+    }
+
+    #[test]
+    fn incomplete_trailing_characters_are_held_back() {
+        assert_eq!(complete_utf8_len(b"abc"), 3);
+        assert_eq!(complete_utf8_len("aé".as_bytes()), 3);
+        assert_eq!(complete_utf8_len(&"aé".as_bytes()[..2]), 1);
+        assert_eq!(complete_utf8_len(&"€".as_bytes()[..2]), 0);
+        assert_eq!(complete_utf8_len(&"😀".as_bytes()[..3]), 0);
+        assert_eq!(complete_utf8_len("😀".as_bytes()), 4);
+        // Invalid bytes are not waited on.
+        assert_eq!(complete_utf8_len(b"a\xff"), 2);
+        assert_eq!(complete_utf8_len(b"\x80\x80\x80"), 3);
+    }
+
+    #[test]
+    fn counts_leading_continuation_bytes() {
+        assert_eq!(leading_continuation_bytes(&"é".as_bytes()[1..]), 1);
+        assert_eq!(leading_continuation_bytes(b"\x80\x80\x80\x80"), 3);
+        assert_eq!(leading_continuation_bytes(b"abc"), 0);
+    }
+}
@@ -1,41 +1,277 @@
+mod ansi;
+mod buffer;
+mod persistence;
+mod process;
+mod profile;
+mod recording;
+mod screen;
+mod search;
+mod shell_integration;
+
+pub use buffer::complete_utf8_len;
+pub use process::TerminalSignal;
+pub use screen::{SnapshotFormat, SnapshotOptions};
+pub use search::SearchOptions;
+
 use anyhow::{anyhow, Result};
-use host_api::TerminalSession;
-use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
+use base64::Engine;
+use buffer::{leading_continuation_bytes, MemoryBudget, MemoryCharge, OutputBuffer};
+use host_api::{TerminalExitStatus, TerminalSession};
+use persistence::HistoryStore;
+use portable_pty::{
+    native_pty_system, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize,
+};
+use recording::Recorder;
+use screen::ScreenModel;
 use serde_json::json;
-use std::collections::{BTreeMap, HashMap};
+use shell_integration::{now_ms, CommandTracker, MarkerScanner};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
 use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex as StdMutex};
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot, Mutex};
 use uuid::Uuid;
 
-const MAX_OUTPUT_BYTES: usize = 1_048_576;
+const OUTPUT_CHANNEL_CAPACITY: usize = 1024;
+const EXIT_CHANNEL_CAPACITY: usize = 64;
+const READER_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A chunk of PTY output as read from the session, tagged with the absolute
+/// byte offset of its first byte since the session started.
+#[derive(Debug, Clone)]
+pub struct TerminalOutputChunk {
+    pub session_id: String,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// Parameters for [`TerminalManager::create`]. An empty `command` launches the
+/// user's default shell.
+#[derive(Debug, Clone)]
+pub struct TerminalCreateOptions {
+    pub cwd: String,
+    pub env: HashMap<String, String>,
+    pub cols: u16,
+    pub rows: u16,
+    pub command: Vec<String>,
+    pub login: bool,
+    pub interactive: bool,
+    pub close_on_exit: bool,
+    /// Injects OSC 133 prompt markers into bash sessions; markers emitted by
+    /// other shells' own configuration are recognised either way.
+    pub shell_integration: bool,
+    /// Starts an asciicast recording from the first byte of output.
+    pub record: bool,
+    /// The profile these options were built from, if any.
+    pub profile_id: Option<String>,
+    /// Rows of scrollback kept by the screen model, 1000 by default.
+    pub scrollback_lines: Option<usize>,
+    /// Capacity of the raw output buffer, 1 MiB by default.
+    pub scrollback_bytes: Option<usize>,
+}
+
+impl Default for TerminalCreateOptions {
+    fn default() -> Self {
+        Self {
+            cwd: ".".to_string(),
+            env: HashMap::new(),
+            cols: 120,
+            rows: 30,
+            command: Vec::new(),
+            login: false,
+            interactive: false,
+            close_on_exit: false,
+            shell_integration: true,
+            record: false,
+            profile_id: None,
+            scrollback_lines: None,
+            scrollback_bytes: None,
+        }
+    }
+}
+
+/// How [`TerminalManager::attach`] encodes the returned output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// UTF-8 text. An incomplete multi-byte sequence at the end of the buffer
+    /// is held back until the rest of it arrives.
+    #[default]
+    Text,
+    /// The raw bytes as standard base64.
+    Base64,
+    /// The raw bytes as an array of numbers.
+    Bytes,
+}
+
+impl OutputEncoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" | "utf8" | "utf-8" => Some(Self::Text),
+            "base64" => Some(Self::Base64),
+            "bytes" => Some(Self::Bytes),
+            _ => None,
+        }
+    }
+}
+
+/// Published once a session's process has exited and its remaining output
+/// has been drained into the buffer.
+#[derive(Debug, Clone)]
+pub struct TerminalExit {
+    pub session_id: String,
+    pub status: TerminalExitStatus,
+}
+
+/// Everything the reader thread updates for each chunk of PTY output.
+struct SessionOutput {
+    buffer: OutputBuffer,
+    screen: ScreenModel,
+    markers: MarkerScanner,
+    commands: CommandTracker,
+    recording: Option<Recorder>,
+    screen_memory: MemoryCharge,
+    recording_memory: MemoryCharge,
+}
+
+impl SessionOutput {
+    /// Appends a chunk read at absolute `offset`. The screen is fed up to each
+    /// shell integration marker before the marker is applied, so the screen
+    /// state matches the marker's position in the stream.
+    fn ingest(&mut self, offset: u64, bytes: &[u8]) {
+        self.buffer.push(bytes);
+        if let Some(recorder) = self.recording.as_mut() {
+            recorder.output(bytes);
+        }
+        let mut fed = 0;
+        for hit in self.markers.scan(offset, bytes) {
+            let end = (hit.end - offset) as usize;
+            self.screen.process(&bytes[fed..end]);
+            fed = end;
+            self.commands.apply(hit, &self.buffer);
+        }
+        self.screen.process(&bytes[fed..]);
+        self.measure();
+    }
+
+    /// Charges the screen model and recording to the memory budget.
+    fn measure(&mut self) {
+        self.screen_memory.set(self.screen.memory_bytes());
+        self.recording_memory
+            .set(self.recording.as_ref().map_or(0, Recorder::memory_bytes));
+    }
+
+    fn stats(&self) -> serde_json::Value {
+        let mut stats = self.buffer.stats();
+        stats["screenBytes"] = json!(self.screen_memory.bytes());
+        stats["recordingBytes"] = json!(self.recording_memory.bytes());
+        stats
+    }
+}
 
 struct RunningSession {
     meta: TerminalSession,
-    child: Box<dyn portable_pty::Child + Send + Sync>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
     master: Box<dyn MasterPty + Send>,
     stdin: Arc<StdMutex<Box<dyn Write + Send>>>,
-    output: Arc<StdMutex<Vec<u8>>>,
+    output: Arc<StdMutex<SessionOutput>>,
+    output_tx: broadcast::Sender<TerminalOutputChunk>,
+    /// Processes left in the shell's session when it exited.
+    leftovers: Vec<process::ProcessId>,
 }
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct TerminalManager {
     sessions: Arc<Mutex<HashMap<String, RunningSession>>>,
+    exits: broadcast::Sender<TerminalExit>,
+    history: Option<HistoryStore>,
+    budget: Arc<MemoryBudget>,
+    /// Where the bash integration script goes; a fresh temp dir by default.
+    integration_dir: PathBuf,
+    /// The script, written on first use.
+    bash_rcfile: Arc<StdMutex<Option<PathBuf>>>,
+}
+
+impl Default for TerminalManager {
+    fn default() -> Self {
+        let (exits, _) = broadcast::channel(EXIT_CHANNEL_CAPACITY);
+        Self {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            exits,
+            history: None,
+            budget: Arc::new(MemoryBudget::new(buffer::DEFAULT_MEMORY_BUDGET_BYTES)),
+            integration_dir: std::env::temp_dir()
+                .join(format!("codex-terminal-{}", Uuid::new_v4())),
+            bash_rcfile: Arc::new(StdMutex::new(None)),
+        }
+    }
 }
 
 impl TerminalManager {
-    pub async fn create(
-        &self,
-        cwd: String,
-        env: HashMap<String, String>,
-        cols: u16,
-        rows: u16,
-    ) -> Result<TerminalSession> {
+    /// Creates a manager that keeps each session's metadata and bounded
+    /// scrollback under `dir`, so it can be shown read-only after a restart.
+    pub fn with_history(dir: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            history: Some(HistoryStore::new(&dir)?),
+            integration_dir: dir.as_ref().join("shell-integration"),
+            ..Self::default()
+        })
+    }
+
+    /// Sets the soft limit on memory held across all sessions (64 MiB by
+    /// default), counting output buffers, screen models and recordings. While
+    /// over it, sessions that produce output evict their oldest bytes first.
+    pub fn set_memory_budget(&self, bytes: usize) {
+        self.budget.set_limit(bytes);
+    }
+
+    /// Reports the memory budget and, per live session, the bytes held in and
+    /// dropped from its output buffer and held by its screen and recording.
+    pub async fn stats(&self) -> Result<serde_json::Value> {
+        let sessions = self.sessions.lock().await;
+        let mut items = sessions
+            .values()
+            .map(|session| {
+                let output = session
+                    .output
+                    .lock()
+                    .map_err(|_| anyhow!("failed to lock terminal output"))?;
+                let mut item = output.stats();
+                item["id"] = json!(session.meta.id);
+                Ok((session.meta.created_at_ms, item))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        items.sort_by_key(|(created_at_ms, _)| *created_at_ms);
+        let items = items.into_iter().map(|(_, item)| item).collect::<Vec<_>>();
+        Ok(json!({
+            "budgetBytes": self.budget.limit(),
+            "usedBytes": self.budget.used(),
+            "sessions": items,
+        }))
+    }
+
+    pub async fn create(&self, options: TerminalCreateOptions) -> Result<TerminalSession> {
+        let TerminalCreateOptions {
+            cwd,
+            env,
+            cols,
+            rows,
+            command,
+            login,
+            interactive,
+            close_on_exit,
+            shell_integration,
+            record,
+            profile_id,
+            scrollback_lines,
+            scrollback_bytes,
+        } = options;
         let id = Uuid::new_v4().to_string();
-        let shell = if cfg!(windows) {
-            std::env::var("ComSpec").unwrap_or_else(|_| "cmd.exe".to_string())
+        let command = if command.is_empty() {
+            vec![default_shell()]
         } else {
-            std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string())
+            command
         };
         let pty_system = native_pty_system();
         let pty_pair = pty_system.openpty(PtySize {
@@ -45,17 +281,40 @@ impl TerminalManager {
             pixel_height: 0,
         })?;
 
-        let mut cmd = CommandBuilder::new(shell);
+        let rcfile = if shell_integration && !login && is_plain_bash(&command) {
+            Some(self.bash_rcfile()?)
+        } else {
+            None
+        };
+        let mut cmd = build_command(&command, login, interactive, rcfile.as_deref());
         cmd.cwd(cwd.clone());
         for (key, value) in &env {
             cmd.env(key, value);
         }
 
-        let child = pty_pair.slave.spawn_command(cmd)?;
+        let mut child = pty_pair.slave.spawn_command(cmd)?;
+        let pid = child.process_id();
+        let killer = child.clone_killer();
         let reader = pty_pair.master.try_clone_reader()?;
         let stdin = pty_pair.master.take_writer()?;
-        let output = Arc::new(StdMutex::new(Vec::<u8>::new()));
+        let output = Arc::new(StdMutex::new(SessionOutput {
+            buffer: OutputBuffer::new(scrollback_bytes, Arc::clone(&self.budget)),
+            screen: ScreenModel::new(rows, cols, scrollback_lines),
+            markers: MarkerScanner::default(),
+            commands: CommandTracker::new(cwd.clone()),
+            recording: record.then(|| Recorder::new(cols, rows)),
+            screen_memory: MemoryCharge::new(Arc::clone(&self.budget)),
+            recording_memory: MemoryCharge::new(Arc::clone(&self.budget)),
+        }));
         let output_clone = Arc::clone(&output);
+        let (output_tx, _) = broadcast::channel(OUTPUT_CHANNEL_CAPACITY);
+        let output_tx_clone = output_tx.clone();
+        let session_id = id.clone();
+        let (reader_done_tx, reader_done_rx) = oneshot::channel::<()>();
+        let mut log = match &self.history {
+            Some(history) => Some(history.open_log(&id)?),
+            None => None,
+        };
 
         std::thread::spawn(move || {
             let mut reader = reader;
@@ -64,60 +323,178 @@ impl TerminalManager {
                 match reader.read(&mut chunk) {
                     Ok(0) => break,
                     Ok(count) => {
-                        if let Ok(mut data) = output_clone.lock() {
-                            if count >= MAX_OUTPUT_BYTES {
-                                data.clear();
-                                data.extend_from_slice(&chunk[count - MAX_OUTPUT_BYTES..count]);
-                                continue;
-                            }
-                            let overflow = data
-                                .len()
-                                .saturating_add(count)
-                                .saturating_sub(MAX_OUTPUT_BYTES);
-                            if overflow > 0 {
-                                let current_len = data.len();
-                                let drop_count = overflow.min(current_len);
-                                data.drain(..drop_count);
+                        if let Some(file) = log.as_mut() {
+                            if file.append(&chunk[..count]).is_err() {
+                                log = None;
                             }
-                            data.extend_from_slice(&chunk[..count]);
+                        }
+                        if let Ok(mut output) = output_clone.lock() {
+                            let offset = output.buffer.end_offset();
+                            output.ingest(offset, &chunk[..count]);
+                            // Publish while holding the buffer lock so that a reader
+                            // combining `attach` and `subscribe` sees a gapless stream.
+                            let _ = output_tx_clone.send(TerminalOutputChunk {
+                                session_id: session_id.clone(),
+                                offset,
+                                data: chunk[..count].to_vec(),
+                            });
                         }
                     }
                     Err(_) => break,
                 }
             }
+            let _ = reader_done_tx.send(());
         });
 
         let session = TerminalSession {
             id: id.clone(),
+            label: None,
+            pid,
+            created_at_ms: now_ms(),
             cwd,
             env: env.into_iter().collect::<BTreeMap<String, String>>(),
             cols,
             rows,
+            command,
+            login,
+            interactive,
+            close_on_exit,
+            shell_integration,
+            profile_id,
+            scrollback_lines,
+            scrollback_bytes,
+            exit_status: None,
         };
-
-        self.sessions.lock().await.insert(
+        if let Some(history) = &self.history {
+            history.save_meta(&session)?;
+        }
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(
             id.clone(),
             RunningSession {
                 meta: session.clone(),
-                child,
+                killer,
                 master: pty_pair.master,
                 stdin: Arc::new(StdMutex::new(stdin)),
                 output,
+                output_tx,
+                leftovers: Vec::new(),
             },
         );
+        let open_ids = sessions.keys().cloned().collect::<HashSet<String>>();
+        drop(sessions);
+        if let Some(history) = &self.history {
+            // Pruning touches the disk, so it runs outside the sessions lock;
+            // the session is already running, so a failure here is not fatal.
+            let _ = history.prune(|other| open_ids.contains(other));
+        }
+
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let waited = tokio::task::spawn_blocking(move || {
+                let status = child.wait();
+                // Taken right after reaping, before the shell's pid can be
+                // reused, so `close` can later tell its leftovers apart.
+                (status, session_leftovers(pid))
+            })
+            .await;
+            let (status, leftovers) = match waited {
+                Ok((Ok(status), leftovers)) => (exit_status_from_pty(&status), leftovers),
+                _ => (
+                    TerminalExitStatus {
+                        exit_code: 1,
+                        signal: None,
+                        success: false,
+                    },
+                    Vec::new(),
+                ),
+            };
+            // Let the reader drain what the process wrote before exiting so that
+            // `attach` after the exit event returns the complete output.
+            let _ = tokio::time::timeout(READER_DRAIN_TIMEOUT, reader_done_rx).await;
+            manager.mark_exited(&id, status, leftovers).await;
+        });
+
         Ok(session)
     }
 
+    fn bash_rcfile(&self) -> Result<PathBuf> {
+        let mut rcfile = self
+            .bash_rcfile
+            .lock()
+            .map_err(|_| anyhow!("failed to lock shell integration"))?;
+        if let Some(path) = rcfile.as_ref() {
+            return Ok(path.clone());
+        }
+        let path = shell_integration::write_bash_rcfile(&self.integration_dir)?;
+        *rcfile = Some(path.clone());
+        Ok(path)
+    }
+
+    /// Subscribes to exit notifications for every session of this manager.
+    pub fn subscribe_exits(&self) -> broadcast::Receiver<TerminalExit> {
+        self.exits.subscribe()
+    }
+
+    async fn mark_exited(
+        &self,
+        id: &str,
+        status: TerminalExitStatus,
+        leftovers: Vec<process::ProcessId>,
+    ) {
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(id) else {
+            return;
+        };
+        session.meta.exit_status = Some(status.clone());
+        session.leftovers = leftovers;
+        if let Some(history) = &self.history {
+            let _ = history.save_meta(&session.meta);
+        }
+        if session.meta.close_on_exit {
+            sessions.remove(id);
+        }
+        let _ = self.exits.send(TerminalExit {
+            session_id: id.to_string(),
+            status,
+        });
+    }
+
+    /// Subscribes to live output of a session. Chunks carry absolute byte
+    /// offsets; a gap between consecutive offsets means the receiver lagged.
+    pub async fn subscribe(&self, id: &str) -> Result<broadcast::Receiver<TerminalOutputChunk>> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        Ok(session.output_tx.subscribe())
+    }
+
     pub async fn write(&self, id: &str, text: &str) -> Result<()> {
+        self.write_bytes(id, text.as_bytes()).await
+    }
+
+    /// Writes raw bytes to the session's input, e.g. control sequences that
+    /// are not valid UTF-8.
+    pub async fn write_bytes(&self, id: &str, bytes: &[u8]) -> Result<()> {
         let mut sessions = self.sessions.lock().await;
         let session = sessions
             .get_mut(id)
             .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        if session.meta.exit_status.is_some() {
+            return Err(anyhow!("terminal session {id} has exited"));
+        }
         let mut stdin = session
             .stdin
             .lock()
             .map_err(|_| anyhow!("failed to lock terminal stdin"))?;
-        stdin.write_all(text.as_bytes())?;
+        // Record before writing so the input precedes its echo in the cast.
+        if let Ok(mut output) = session.output.lock() {
+            if let Some(recorder) = output.recording.as_mut() {
+                recorder.input(bytes);
+            }
+        }
+        stdin.write_all(bytes)?;
         stdin.flush()?;
         Ok(())
     }
@@ -129,25 +506,158 @@ impl TerminalManager {
             .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
         session.meta.cols = cols;
         session.meta.rows = rows;
+        if let Some(history) = &self.history {
+            history.save_meta(&session.meta)?;
+        }
         session.master.resize(PtySize {
             rows,
             cols,
             pixel_width: 0,
             pixel_height: 0,
         })?;
+        if let Ok(mut output) = session.output.lock() {
+            output.screen.resize(rows, cols);
+            if let Some(recorder) = output.recording.as_mut() {
+                recorder.resize(cols, rows);
+            }
+            output.measure();
+        }
         Ok(())
     }
 
+    /// Sends `signal` to the session's foreground process group (falling back
+    /// to the shell itself), or with `tree` to every process descending from
+    /// the shell.
+    pub async fn signal(&self, id: &str, signal: TerminalSignal, tree: bool) -> Result<()> {
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        if session.meta.exit_status.is_some() {
+            return Err(anyhow!("terminal session {id} has exited"));
+        }
+        #[cfg(unix)]
+        {
+            let pid = session
+                .meta
+                .pid
+                .ok_or_else(|| anyhow!("terminal session {id} has no process id"))?
+                as i32;
+            if tree {
+                process::signal_all(&process::process_tree(pid), signal);
+            } else {
+                let group = session.master.process_group_leader().unwrap_or(pid);
+                process::signal_group(group, signal)?;
+            }
+            Ok(())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tree;
+            if signal != TerminalSignal::Kill {
+                return Err(anyhow!("signals are not supported on this platform"));
+            }
+            session.killer.kill()?;
+            Ok(())
+        }
+    }
+
+    /// Kills the session's whole process tree, so background jobs and servers
+    /// started from the shell don't outlive it, and forgets the session.
     pub async fn close(&self, id: &str) -> Result<()> {
         let mut sessions = self.sessions.lock().await;
         let mut session = sessions
             .remove(id)
             .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
-        session.child.kill()?;
+        #[cfg(unix)]
+        if let Some(pid) = session.meta.pid {
+            let pid = pid as i32;
+            let targets = if session.meta.exit_status.is_some() {
+                // The shell was reaped, so its pid (and with it the session id)
+                // may have been reused. It stays reserved while any process is
+                // left in the session, so the session is still ours only if one
+                // of the leftovers seen at exit is still alive.
+                if process::still_running(&session.leftovers).is_empty() {
+                    Vec::new()
+                } else {
+                    process::session_members(pid)
+                }
+            } else {
+                process::process_tree(pid)
+            };
+            process::signal_all(&targets, TerminalSignal::Kill);
+        }
+        if session.meta.exit_status.is_none() {
+            // The tree kill above may already have taken the shell down.
+            let _ = session.killer.kill();
+        }
         Ok(())
     }
 
-    pub async fn attach(&self, id: &str) -> Result<serde_json::Value> {
+    /// Sends SIGHUP to the session's process tree and waits up to `timeout`
+    /// for the shell to exit before escalating to [`TerminalManager::close`].
+    /// Processes that were part of the tree are killed even if they were
+    /// reparented in the meantime.
+    pub async fn close_gracefully(&self, id: &str, timeout: Duration) -> Result<()> {
+        let mut exits = self.subscribe_exits();
+        let (pid, exited) = {
+            let sessions = self.sessions.lock().await;
+            let session = sessions
+                .get(id)
+                .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+            (session.meta.pid, session.meta.exit_status.is_some())
+        };
+        #[cfg(unix)]
+        let tree = pid
+            .filter(|_| !exited)
+            .map(|pid| process::identify(&process::process_tree(pid as i32)))
+            .unwrap_or_default();
+        #[cfg(not(unix))]
+        let _ = pid;
+        if !exited {
+            #[cfg(unix)]
+            process::signal_all(&process::still_running(&tree), TerminalSignal::Hangup);
+            let _ = tokio::time::timeout(timeout, async {
+                loop {
+                    match exits.recv().await {
+                        Ok(exit) if exit.session_id == id => break,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                        _ => {}
+                    }
+                }
+            })
+            .await;
+        }
+        // Only processes whose start time still matches are killed; the rest
+        // may have exited and had their pids reused during the wait. The shell
+        // itself is left to `close`, which knows whether it has exited.
+        #[cfg(unix)]
+        {
+            let shell = pid.map(|pid| pid as i32);
+            let survivors = process::still_running(&tree)
+                .into_iter()
+                .filter(|&survivor| Some(survivor) != shell)
+                .collect::<Vec<i32>>();
+            process::signal_all(&survivors, TerminalSignal::Kill);
+        }
+        match self.close(id).await {
+            // Sessions created with `close_on_exit` are gone once they exit.
+            Err(_) if !self.sessions.lock().await.contains_key(id) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Returns buffered output produced at or after `since` (the whole retained
+    /// buffer when `None`). `truncated` is set when part of the requested range
+    /// was already evicted, so the caller should reset its view before applying.
+    /// `byteLength` counts the bytes represented in `output`; resuming from
+    /// `offset + byteLength` never splits a character in text mode.
+    pub async fn attach(
+        &self,
+        id: &str,
+        since: Option<u64>,
+        encoding: OutputEncoding,
+    ) -> Result<serde_json::Value> {
         let sessions = self.sessions.lock().await;
         let session = sessions
             .get(id)
@@ -156,11 +666,468 @@ impl TerminalManager {
             .output
             .lock()
             .map_err(|_| anyhow!("failed to lock terminal output"))?;
-        let text = String::from_utf8_lossy(output.as_slice()).to_string();
+        let buffer = &output.buffer;
+        let requested = since.unwrap_or(buffer.start_offset());
+        let (mut offset, retained) = buffer.since(requested);
+        let mut bytes: &[u8] = &retained;
+        let output = match encoding {
+            OutputEncoding::Text => {
+                let skip = leading_continuation_bytes(bytes);
+                offset += skip as u64;
+                bytes = &bytes[skip..];
+                bytes = &bytes[..complete_utf8_len(bytes)];
+                json!(String::from_utf8_lossy(bytes))
+            }
+            OutputEncoding::Base64 => {
+                json!(base64::engine::general_purpose::STANDARD.encode(bytes))
+            }
+            OutputEncoding::Bytes => json!(bytes),
+        };
         Ok(json!({
             "session": session.meta,
-            "output": text,
+            "output": output,
+            "encoding": match encoding {
+                OutputEncoding::Text => "text",
+                OutputEncoding::Base64 => "base64",
+                OutputEncoding::Bytes => "bytes",
+            },
+            "byteLength": bytes.len(),
+            "offset": offset,
+            "startOffset": buffer.start_offset(),
+            "endOffset": buffer.end_offset(),
+            "truncated": requested < buffer.start_offset(),
+        }))
+    }
+
+    /// Lists live sessions in creation order with their current window title
+    /// and foreground process.
+    pub async fn list(&self) -> Result<serde_json::Value> {
+        let sessions = self.sessions.lock().await;
+        let mut items = sessions
+            .values()
+            .map(|session| {
+                let title = session
+                    .output
+                    .lock()
+                    .ok()
+                    .and_then(|output| output.screen.title().map(ToString::to_string));
+                let foreground = if session.meta.exit_status.is_none() {
+                    foreground_process(session.master.as_ref())
+                } else {
+                    None
+                };
+                let mut item = serde_json::to_value(&session.meta)?;
+                item["title"] = json!(title);
+                item["foregroundProcess"] = json!(foreground);
+                Ok((session.meta.created_at_ms, item))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        items.sort_by_key(|(created_at_ms, _)| *created_at_ms);
+        let items = items.into_iter().map(|(_, item)| item).collect::<Vec<_>>();
+        Ok(json!({ "sessions": items }))
+    }
+
+    /// Sets (or clears, with `None`) the user-facing label of a session.
+    pub async fn set_label(&self, id: &str, label: Option<String>) -> Result<TerminalSession> {
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        session.meta.label = label
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty());
+        if let Some(history) = &self.history {
+            history.save_meta(&session.meta)?;
+        }
+        Ok(session.meta.clone())
+    }
+
+    /// Starts (or restarts, discarding previous events) an asciicast
+    /// recording of the session's output, input and resizes.
+    pub async fn start_recording(&self, id: &str) -> Result<serde_json::Value> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        let mut output = session
+            .output
+            .lock()
+            .map_err(|_| anyhow!("failed to lock terminal output"))?;
+        let recorder = Recorder::new(session.meta.cols, session.meta.rows);
+        let status = recorder.status();
+        output.recording = Some(recorder);
+        output.measure();
+        Ok(status)
+    }
+
+    /// Stops recording; the captured events stay available for export.
+    pub async fn stop_recording(&self, id: &str) -> Result<serde_json::Value> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        let mut output = session
+            .output
+            .lock()
+            .map_err(|_| anyhow!("failed to lock terminal output"))?;
+        let recorder = output
+            .recording
+            .as_mut()
+            .ok_or_else(|| anyhow!("terminal session {id} is not being recorded"))?;
+        recorder.stop();
+        Ok(recorder.status())
+    }
+
+    /// Renders the session's recording as an asciicast v2 document.
+    pub async fn export_recording(&self, id: &str) -> Result<String> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        let output = session
+            .output
+            .lock()
+            .map_err(|_| anyhow!("failed to lock terminal output"))?;
+        let recorder = output
+            .recording
+            .as_ref()
+            .ok_or_else(|| anyhow!("terminal session {id} has no recording"))?;
+        let title = session.meta.label.as_deref().or(output.screen.title());
+        Ok(recorder.to_asciicast(&session.meta.env, title))
+    }
+
+    /// Lists persisted sessions, most recent first. `live` marks sessions that
+    /// are still running in this process.
+    pub async fn history(&self) -> Result<serde_json::Value> {
+        let Some(history) = &self.history else {
+            return Ok(json!({ "sessions": [] }));
+        };
+        let sessions = self.sessions.lock().await;
+        let items = history
+            .list()?
+            .into_iter()
+            .map(|meta| {
+                let live = sessions.contains_key(&meta.id);
+                let mut item = serde_json::to_value(meta)?;
+                item["live"] = json!(live);
+                Ok(item)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(json!({ "sessions": items }))
+    }
+
+    /// Returns the persisted scrollback of a (possibly previous-run) session.
+    pub async fn history_output(&self, id: &str) -> Result<serde_json::Value> {
+        let history = self.require_history()?;
+        let meta = history.load(id)?;
+        let output = history.read_output(id)?;
+        Ok(json!({
+            "session": meta,
+            "output": String::from_utf8_lossy(&output),
             "byteLength": output.len(),
         }))
     }
+
+    pub async fn forget_history(&self, id: &str) -> Result<()> {
+        self.require_history()?.remove(id)
+    }
+
+    /// Starts a new session with the cwd, env, command and size of a live or
+    /// persisted session.
+    pub async fn restart(&self, id: &str) -> Result<TerminalSession> {
+        let live = self
+            .sessions
+            .lock()
+            .await
+            .get(id)
+            .map(|session| session.meta.clone());
+        let meta = match live {
+            Some(meta) => meta,
+            None => self.require_history()?.load(id)?,
+        };
+        let label = meta.label.clone();
+        let session = self
+            .create(TerminalCreateOptions {
+                cwd: meta.cwd,
+                env: meta.env.into_iter().collect(),
+                cols: meta.cols,
+                rows: meta.rows,
+                command: meta.command,
+                login: meta.login,
+                interactive: meta.interactive,
+                close_on_exit: meta.close_on_exit,
+                shell_integration: meta.shell_integration,
+                record: false,
+                profile_id: meta.profile_id,
+                scrollback_lines: meta.scrollback_lines,
+                scrollback_bytes: meta.scrollback_bytes,
+            })
+            .await?;
+        if label.is_none() {
+            return Ok(session);
+        }
+        self.set_label(&session.id, label).await
+    }
+
+    fn require_history(&self) -> Result<&HistoryStore> {
+        self.history
+            .as_ref()
+            .ok_or_else(|| anyhow!("terminal history is disabled"))
+    }
+
+    /// Lists commands recognised through shell integration markers, oldest
+    /// first. The `limit` most recent are returned; with `include_output`
+    /// each command carries its output text when still buffered.
+    pub async fn commands(
+        &self,
+        id: &str,
+        limit: Option<usize>,
+        include_output: bool,
+    ) -> Result<serde_json::Value> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        let output = session
+            .output
+            .lock()
+            .map_err(|_| anyhow!("failed to lock terminal output"))?;
+        let commands = output.commands.commands();
+        let skip = limit.map_or(0, |limit| commands.len().saturating_sub(limit));
+        let items = commands
+            .into_iter()
+            .skip(skip)
+            .map(|command| {
+                let mut item = serde_json::to_value(&command)?;
+                if include_output {
+                    let end = command
+                        .output_end
+                        .unwrap_or_else(|| output.buffer.end_offset());
+                    item["output"] = match output.buffer.range(command.output_start, end) {
+                        Some(bytes) => json!(ansi::strip_ansi(&bytes)),
+                        None => serde_json::Value::Null,
+                    };
+                }
+                Ok(item)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(json!({
+            "session": session.meta,
+            "commands": items,
+        }))
+    }
+
+    /// Searches the retained output of session `id`, or of every live session
+    /// in creation order, with ANSI escapes stripped. `truncated` is set when
+    /// more than `max_results` matches exist.
+    pub async fn search(
+        &self,
+        id: Option<&str>,
+        options: SearchOptions,
+    ) -> Result<serde_json::Value> {
+        let pattern = options.compile()?;
+        let mut targets = {
+            let sessions = self.sessions.lock().await;
+            let targets = match id {
+                Some(id) => vec![sessions
+                    .get(id)
+                    .ok_or_else(|| anyhow!("unknown terminal session {id}"))?],
+                None => sessions.values().collect::<Vec<_>>(),
+            };
+            targets
+                .into_iter()
+                .map(|session| {
+                    let meta = &session.meta;
+                    (
+                        meta.created_at_ms,
+                        meta.id.clone(),
+                        Arc::clone(&session.output),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        targets.sort_by_key(|(created_at_ms, _, _)| *created_at_ms);
+        let mut matches = Vec::new();
+        let mut truncated = false;
+        for (_, session_id, output) in targets {
+            // Copy the buffer out so the scan holds no lock the reader needs.
+            let (start, bytes) = {
+                let output = output
+                    .lock()
+                    .map_err(|_| anyhow!("failed to lock terminal output"))?;
+                let (start, bytes) = output.buffer.since(0);
+                (start, bytes.into_owned())
+            };
+            let remaining = options.max_results - matches.len();
+            let found = search::search_output(
+                &session_id,
+                &bytes,
+                start,
+                &pattern,
+                options.context_lines,
+                remaining + 1,
+            );
+            if found.len() > remaining {
+                matches.extend(found.into_iter().take(remaining));
+                truncated = true;
+                break;
+            }
+            matches.extend(found);
+        }
+        Ok(json!({
+            "matches": matches,
+            "truncated": truncated,
+        }))
+    }
+
+    /// Renders the emulated screen (and optionally its scrollback) as text or
+    /// styled attribute runs, as a terminal would currently display it.
+    pub async fn snapshot(&self, id: &str, options: SnapshotOptions) -> Result<serde_json::Value> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions
+            .get(id)
+            .ok_or_else(|| anyhow!("unknown terminal session {id}"))?;
+        let mut output = session
+            .output
+            .lock()
+            .map_err(|_| anyhow!("failed to lock terminal output"))?;
+        let mut snapshot = output.screen.snapshot(options);
+        snapshot["session"] = serde_json::to_value(&session.meta)?;
+        snapshot["offset"] = json!(output.buffer.end_offset());
+        Ok(snapshot)
+    }
+}
+
+/// Processes left in the session of the shell `pid`, which was just reaped.
+fn session_leftovers(pid: Option<u32>) -> Vec<process::ProcessId> {
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        return process::identify(&process::session_members(pid as i32));
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+    Vec::new()
+}
+
+pub(crate) fn default_shell() -> String {
+    if cfg!(windows) {
+        std::env::var("ComSpec").unwrap_or_else(|_| "cmd.exe".to_string())
+    } else {
+        std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string())
+    }
+}
+
+/// Builds the PTY command for `argv`. Login/interactive flags apply to the
+/// shell directly when `argv` is just the default shell; other programs are
+/// exec'd through `$SHELL -l -i -c` so they inherit the login environment,
+/// or through `/bin/sh` when `$SHELL` is not a POSIX shell (fish, nushell).
+/// `rcfile` is passed to bash as `--rcfile`, ahead of its short options.
+fn build_command(
+    argv: &[String],
+    login: bool,
+    interactive: bool,
+    rcfile: Option<&Path>,
+) -> CommandBuilder {
+    let rcfile_args = rcfile
+        .map(|path| vec![OsString::from("--rcfile"), path.as_os_str().to_owned()])
+        .unwrap_or_default();
+    if cfg!(windows) || (!login && !interactive) {
+        let mut cmd = CommandBuilder::from_argv(argv.iter().map(Into::into).collect());
+        cmd.args(&rcfile_args);
+        return cmd;
+    }
+    let mut flags = Vec::new();
+    if login {
+        flags.push("-l");
+    }
+    if interactive {
+        flags.push("-i");
+    }
+    let shell = default_shell();
+    if argv.len() == 1 && argv[0] == shell {
+        let mut cmd = CommandBuilder::new(&shell);
+        cmd.args(&rcfile_args);
+        cmd.args(&flags);
+        return cmd;
+    }
+    let wrapper = if is_posix_shell(&shell) {
+        shell.as_str()
+    } else {
+        "/bin/sh"
+    };
+    let mut cmd = CommandBuilder::new(wrapper);
+    cmd.args(&flags);
+    // `exec "$0" "$@"` keeps the original argv intact without any quoting.
+    cmd.arg("-c");
+    cmd.arg("exec \"$0\" \"$@\"");
+    cmd.args(argv);
+    cmd.args(&rcfile_args);
+    cmd
+}
+
+/// The process group currently in the terminal's foreground, e.g. the job a
+/// shell is running, with its executable name where the platform exposes it.
+#[cfg(unix)]
+fn foreground_process(master: &(dyn MasterPty + Send)) -> Option<serde_json::Value> {
+    let pid = master.process_group_leader()?;
+    Some(json!({
+        "pid": pid,
+        "name": process_name(pid),
+    }))
+}
+
+#[cfg(not(unix))]
+fn foreground_process(_master: &(dyn MasterPty + Send)) -> Option<serde_json::Value> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn process_name(pid: i32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        .ok()
+        .map(|name| name.trim().to_string())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_name(pid: i32) -> Option<String> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "comm=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Whether `shell` understands the POSIX `-c` wrapper used by
+/// [`build_command`].
+fn is_posix_shell(shell: &str) -> bool {
+    Path::new(shell)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| {
+            matches!(
+                name,
+                "sh" | "bash" | "zsh" | "dash" | "ksh" | "mksh" | "ash"
+            )
+        })
+}
+
+fn is_plain_bash(argv: &[String]) -> bool {
+    argv.len() == 1
+        && Path::new(&argv[0])
+            .file_name()
+            .is_some_and(|name| name == "bash")
+}
+
+fn exit_status_from_pty(status: &ExitStatus) -> TerminalExitStatus {
+    // portable-pty only exposes the signal name through its Display impl.
+    let signal = status
+        .to_string()
+        .strip_prefix("Terminated by ")
+        .map(ToString::to_string);
+    TerminalExitStatus {
+        exit_code: status.exit_code(),
+        signal,
+        success: status.success(),
+    }
 }
@@ -0,0 +1,194 @@
+use crate::buffer::leading_continuation_bytes;
+use anyhow::{anyhow, Result};
+use host_api::TerminalSession;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAX_LOG_BYTES: u64 = 1_048_576;
+const MAX_SESSIONS: usize = 20;
+
+/// On-disk history of terminal sessions: `<id>.json` holds the session
+/// metadata and `<id>.log` the most recent raw output. Both can hold secrets
+/// (environment variables, typed passwords), so the directory and files are
+/// private to the user.
+#[derive(Clone)]
+pub(crate) struct HistoryStore {
+    dir: PathBuf,
+}
+
+impl HistoryStore {
+    pub(crate) fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+            builder.mode(0o700);
+            builder.create(&dir)?;
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        }
+        #[cfg(not(unix))]
+        builder.create(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub(crate) fn save_meta(&self, meta: &TerminalSession) -> Result<()> {
+        let path = self.path_for(&meta.id, "json")?;
+        private_file()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?
+            .write_all(&serde_json::to_vec_pretty(meta)?)?;
+        Ok(())
+    }
+
+    pub(crate) fn open_log(&self, id: &str) -> Result<SessionLog> {
+        let path = self.path_for(id, "log")?;
+        let file = private_file()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        Ok(SessionLog { file, len: 0 })
+    }
+
+    /// Persisted sessions, most recently modified first.
+    pub(crate) fn list(&self) -> Result<Vec<TerminalSession>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            let Ok(contents) = fs::read(&path) else {
+                continue;
+            };
+            if let Ok(session) = serde_json::from_slice::<TerminalSession>(&contents) {
+                entries.push((modified, session));
+            }
+        }
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+        Ok(entries.into_iter().map(|(_, session)| session).collect())
+    }
+
+    pub(crate) fn load(&self, id: &str) -> Result<TerminalSession> {
+        let contents = fs::read(self.path_for(id, "json")?)
+            .map_err(|_| anyhow!("no terminal history for session {id}"))?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    pub(crate) fn read_output(&self, id: &str) -> Result<Vec<u8>> {
+        let path = self.path_for(id, "log")?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let seeked = len > MAX_LOG_BYTES;
+        if seeked {
+            file.seek(SeekFrom::Start(len - MAX_LOG_BYTES))?;
+        }
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        if seeked {
+            data.drain(..leading_continuation_bytes(&data));
+        }
+        Ok(data)
+    }
+
+    pub(crate) fn remove(&self, id: &str) -> Result<()> {
+        for ext in ["json", "log"] {
+            let path = self.path_for(id, ext)?;
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the oldest sessions beyond the retention limit, except those
+    /// `is_open` reports as still running; their logs are being written to.
+    pub(crate) fn prune(&self, is_open: impl Fn(&str) -> bool) -> Result<()> {
+        for session in self.list()?.into_iter().skip(MAX_SESSIONS) {
+            if !is_open(&session.id) {
+                self.remove(&session.id)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn path_for(&self, id: &str, ext: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-') {
+            return Err(anyhow!("invalid terminal session id"));
+        }
+        Ok(self.dir.join(format!("{id}.{ext}")))
+    }
+}
+
+fn private_file() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+}
+
+/// Append-only output log that keeps roughly the last `MAX_LOG_BYTES` bytes by
+/// rewriting its tail once it grows to twice that size.
+pub(crate) struct SessionLog {
+    file: File,
+    len: u64,
+}
+
+impl SessionLog {
+    pub(crate) fn append(&mut self, bytes: &[u8]) -> Result<()> {
+        self.file.write_all(bytes)?;
+        self.len += bytes.len() as u64;
+        if self.len > MAX_LOG_BYTES * 2 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        let mut tail = Vec::with_capacity(MAX_LOG_BYTES as usize);
+        self.file.seek(SeekFrom::Start(self.len - MAX_LOG_BYTES))?;
+        self.file.read_to_end(&mut tail)?;
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&tail)?;
+        self.len = tail.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn history_is_private_to_the_user() {
+        let dir = std::env::temp_dir().join(format!("codex-history-{}", uuid::Uuid::new_v4()));
+        let store = HistoryStore::new(&dir).unwrap();
+        let id = uuid::Uuid::new_v4().to_string();
+        let meta = serde_json::json!({ "id": id, "cwd": "/", "cols": 80, "rows": 24 });
+        store
+            .save_meta(&serde_json::from_value(meta).unwrap())
+            .unwrap();
+        store.open_log(&id).unwrap().append(b"secret").unwrap();
+
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&dir.join(format!("{id}.json"))), 0o600);
+        assert_eq!(mode(&dir.join(format!("{id}.log"))), 0o600);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+//! Signal delivery to terminal process groups and process trees.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalSignal {
+    Interrupt,
+    Terminate,
+    Hangup,
+    Kill,
+}
+
+impl TerminalSignal {
+    /// Accepts `SIGINT`, `INT` or `int` style names.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_uppercase();
+        match name.strip_prefix("SIG").unwrap_or(&name) {
+            "INT" => Some(Self::Interrupt),
+            "TERM" => Some(Self::Terminate),
+            "HUP" => Some(Self::Hangup),
+            "KILL" => Some(Self::Kill),
+            _ => None,
+        }
+    }
+
+    #[cfg(unix)]
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Self::Interrupt => libc::SIGINT,
+            Self::Terminate => libc::SIGTERM,
+            Self::Hangup => libc::SIGHUP,
+            Self::Kill => libc::SIGKILL,
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn signal_group(pgid: i32, signal: TerminalSignal) -> std::io::Result<()> {
+    // SAFETY: killpg has no memory-safety preconditions.
+    if unsafe { libc::killpg(pgid, signal.as_raw()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Signals each pid and its process group, ignoring processes that are gone.
+#[cfg(unix)]
+pub(crate) fn signal_all(pids: &[i32], signal: TerminalSignal) {
+    for &pid in pids {
+        // SAFETY: kill/killpg have no memory-safety preconditions.
+        unsafe {
+            libc::killpg(pid, signal.as_raw());
+            libc::kill(pid, signal.as_raw());
+        }
+    }
+}
+
+/// `root` followed by every process descending from it. On Linux processes
+/// that were reparented but still belong to `root`'s session are included.
+#[cfg(unix)]
+pub(crate) fn process_tree(root: i32) -> Vec<i32> {
+    let table = process_table();
+    let mut tree = vec![root];
+    let mut index = 0;
+    while index < tree.len() {
+        let parent = tree[index];
+        for entry in &table {
+            if entry.ppid == parent && !tree.contains(&entry.pid) {
+                tree.push(entry.pid);
+            }
+        }
+        index += 1;
+    }
+    for entry in &table {
+        if entry.sid == Some(root) && !tree.contains(&entry.pid) {
+            tree.push(entry.pid);
+        }
+    }
+    tree
+}
+
+/// Processes still in the session `sid` led, e.g. background jobs left behind
+/// by a shell that has exited. Empty where session ids are not available.
+#[cfg(unix)]
+pub(crate) fn session_members(sid: i32) -> Vec<i32> {
+    process_table()
+        .into_iter()
+        .filter(|entry| entry.sid == Some(sid) && entry.pid != sid)
+        .map(|entry| entry.pid)
+        .collect()
+}
+
+/// A process as seen at one point in time. Its start time tells it apart
+/// from a later process that reused the pid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProcessId {
+    pid: i32,
+    started: Option<u64>,
+}
+
+/// Identifies `pids` by their current start times, skipping those already
+/// gone. Order is preserved.
+#[cfg(unix)]
+pub(crate) fn identify(pids: &[i32]) -> Vec<ProcessId> {
+    let table = process_table();
+    pids.iter()
+        .filter_map(|&pid| table.iter().find(|entry| entry.pid == pid))
+        .map(|entry| ProcessId {
+            pid: entry.pid,
+            started: entry.started,
+        })
+        .collect()
+}
+
+/// The pids among `ids` that still belong to the same processes. Where start
+/// times are not available, a pid that still exists is taken to match.
+#[cfg(unix)]
+pub(crate) fn still_running(ids: &[ProcessId]) -> Vec<i32> {
+    let table = process_table();
+    ids.iter()
+        .filter(|id| {
+            table
+                .iter()
+                .any(|entry| entry.pid == id.pid && entry.started == id.started)
+        })
+        .map(|id| id.pid)
+        .collect()
+}
+
+#[cfg(unix)]
+struct ProcessEntry {
+    pid: i32,
+    ppid: i32,
+    sid: Option<i32>,
+    started: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+fn process_table() -> Vec<ProcessEntry> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse::<i32>().ok()?;
+            let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            // The command name may contain spaces; fields resume after its ')'.
+            let fields = stat[stat.rfind(')')? + 1..]
+                .split_whitespace()
+                .collect::<Vec<&str>>();
+            Some(ProcessEntry {
+                pid,
+                ppid: fields.get(1)?.parse().ok()?,
+                sid: fields.get(3).and_then(|value| value.parse().ok()),
+                started: fields.get(19).and_then(|value| value.parse().ok()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_table() -> Vec<ProcessEntry> {
+    let Ok(output) = std::process::Command::new("ps")
+        .args(["-A", "-o", "pid=,ppid="])
+        .output()
+    else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(ProcessEntry {
+                pid: fields.next()?.parse().ok()?,
+                ppid: fields.next()?.parse().ok()?,
+                sid: None,
+                started: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reused_pids_are_not_taken_for_the_original_process() {
+        let pid = std::process::id() as i32;
+        let ids = identify(&[pid]);
+        assert_eq!(still_running(&ids), vec![pid]);
+
+        let reused = ProcessId {
+            pid,
+            started: ids[0].started.map(|started| started + 1),
+        };
+        assert!(still_running(&[reused]).is_empty());
+    }
+}
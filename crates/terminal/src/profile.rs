@@ -0,0 +1,130 @@
+use crate::{default_shell, TerminalCreateOptions};
+use host_api::TerminalProfile;
+use std::collections::HashMap;
+
+impl TerminalCreateOptions {
+    /// Options for launching `profile`. `cwd` is the directory the caller
+    /// would otherwise have used; it is the fallback working directory and the
+    /// value of `${cwd}` in the profile's cwd template.
+    pub fn from_profile(profile: &TerminalProfile, cwd: Option<&str>) -> Self {
+        let defaults = Self::default();
+        let env = profile
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<HashMap<String, String>>();
+        let base_cwd = cwd.map(ToString::to_string).unwrap_or(defaults.cwd);
+        let cwd = match &profile.cwd {
+            Some(template) => expand_cwd_template(template, &base_cwd, &env),
+            None => base_cwd,
+        };
+        let mut command = Vec::new();
+        if profile.shell.is_some() || !profile.args.is_empty() {
+            command.push(profile.shell.clone().unwrap_or_else(default_shell));
+            command.extend(profile.args.iter().cloned());
+        }
+        Self {
+            cwd,
+            env,
+            cols: profile.cols.unwrap_or(defaults.cols),
+            rows: profile.rows.unwrap_or(defaults.rows),
+            command,
+            profile_id: Some(profile.id.clone()),
+            scrollback_lines: profile.scrollback_lines,
+            scrollback_bytes: profile.scrollback_bytes,
+            ..defaults
+        }
+    }
+
+    /// Applies a caller's `command` and `args`. A non-empty `command`
+    /// replaces the current command line; `args` are appended to whichever
+    /// command line results, falling back to the default shell.
+    pub fn apply_command(&mut self, command: Vec<String>, args: Vec<String>) {
+        if !command.is_empty() {
+            self.command = command;
+        }
+        if !args.is_empty() {
+            if self.command.is_empty() {
+                self.command.push(default_shell());
+            }
+            self.command.extend(args);
+        }
+    }
+}
+
+/// Expands a leading `~` and `${VAR}` references. Variables resolve from
+/// `env`, then the host environment; `${cwd}` is `cwd`. Unknown variables
+/// expand to nothing.
+fn expand_cwd_template(template: &str, cwd: &str, env: &HashMap<String, String>) -> String {
+    let lookup = |name: &str| -> Option<String> {
+        if name == "cwd" {
+            return Some(cwd.to_string());
+        }
+        env.get(name).cloned().or_else(|| std::env::var(name).ok())
+    };
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&lookup("HOME").unwrap_or_default());
+        rest = &rest[1..];
+    }
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + 2 + len];
+        expanded.push_str(&lookup(name).unwrap_or_default());
+        rest = &rest[start + 3 + len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_home_cwd_and_variables() {
+        let env = HashMap::from([
+            ("HOME".to_string(), "/home/me".to_string()),
+            ("PROJECT".to_string(), "app".to_string()),
+        ]);
+        let expand = |template| expand_cwd_template(template, "/work", &env);
+        assert_eq!(expand("~"), "/home/me");
+        assert_eq!(expand("~/src/${PROJECT}"), "/home/me/src/app");
+        assert_eq!(expand("${cwd}/sub"), "/work/sub");
+        assert_eq!(expand("/a/${CODEX_TEST_UNSET_VARIABLE}/b"), "/a//b");
+        assert_eq!(expand("/a/~/b"), "/a/~/b");
+        assert_eq!(expand("/a/${PROJECT"), "/a/${PROJECT");
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn args_alone_go_to_the_default_shell() {
+        let mut options = TerminalCreateOptions::default();
+        options.apply_command(Vec::new(), strings(&["-l"]));
+        assert_eq!(options.command, vec![default_shell(), "-l".to_string()]);
+    }
+
+    #[test]
+    fn args_are_appended_to_the_profile_command() {
+        let profile = TerminalProfile {
+            id: "zsh".to_string(),
+            shell: Some("/bin/zsh".to_string()),
+            args: strings(&["-o", "vi"]),
+            ..Default::default()
+        };
+        let mut options = TerminalCreateOptions::from_profile(&profile, None);
+        options.apply_command(Vec::new(), strings(&["-l"]));
+        assert_eq!(options.command, strings(&["/bin/zsh", "-o", "vi", "-l"]));
+
+        let mut options = TerminalCreateOptions::from_profile(&profile, None);
+        options.apply_command(strings(&["/bin/bash"]), strings(&["-l"]));
+        assert_eq!(options.command, strings(&["/bin/bash", "-l"]));
+    }
+}
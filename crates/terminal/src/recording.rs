@@ -0,0 +1,132 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const MAX_RECORDING_BYTES: usize = 16 * 1_048_576;
+
+/// In-memory asciicast v2 recorder for one session. Recording stops adding
+/// events (and sets `truncated`) once `MAX_RECORDING_BYTES` of event data has
+/// been captured.
+pub(crate) struct Recorder {
+    started: Instant,
+    timestamp: u64,
+    width: u16,
+    height: u16,
+    events: Vec<(f64, &'static str, String)>,
+    bytes: usize,
+    truncated: bool,
+    active: bool,
+    output_carry: Vec<u8>,
+    input_carry: Vec<u8>,
+}
+
+impl Recorder {
+    pub(crate) fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            started: Instant::now(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            width: cols,
+            height: rows,
+            events: Vec::new(),
+            bytes: 0,
+            truncated: false,
+            active: true,
+            output_carry: Vec::new(),
+            input_carry: Vec::new(),
+        }
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.active = false;
+    }
+
+    pub(crate) fn output(&mut self, bytes: &[u8]) {
+        let text = take_utf8(&mut self.output_carry, bytes);
+        self.push("o", text);
+    }
+
+    pub(crate) fn input(&mut self, bytes: &[u8]) {
+        let text = take_utf8(&mut self.input_carry, bytes);
+        self.push("i", text);
+    }
+
+    pub(crate) fn resize(&mut self, cols: u16, rows: u16) {
+        self.push("r", format!("{cols}x{rows}"));
+    }
+
+    fn push(&mut self, kind: &'static str, data: String) {
+        if !self.active || data.is_empty() {
+            return;
+        }
+        if self.bytes + data.len() > MAX_RECORDING_BYTES {
+            self.truncated = true;
+            self.active = false;
+            return;
+        }
+        self.bytes += data.len();
+        self.events
+            .push((self.started.elapsed().as_secs_f64(), kind, data));
+    }
+
+    /// Approximate heap size of the captured events.
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.bytes + self.events.len() * std::mem::size_of::<(f64, &str, String)>()
+    }
+
+    pub(crate) fn status(&self) -> Value {
+        json!({
+            "active": self.active,
+            "eventCount": self.events.len(),
+            "byteLength": self.bytes,
+            "truncated": self.truncated,
+            "timestamp": self.timestamp,
+        })
+    }
+
+    /// Renders the recording as an asciicast v2 document.
+    pub(crate) fn to_asciicast(
+        &self,
+        env: &BTreeMap<String, String>,
+        title: Option<&str>,
+    ) -> String {
+        let mut header = json!({
+            "version": 2,
+            "width": self.width,
+            "height": self.height,
+            "timestamp": self.timestamp,
+            "env": {
+                "SHELL": env.get("SHELL").cloned().or_else(|| std::env::var("SHELL").ok()),
+                "TERM": env.get("TERM").cloned().unwrap_or_else(|| "xterm-256color".to_string()),
+            },
+        });
+        if let Some(title) = title {
+            header["title"] = json!(title);
+        }
+        let mut cast = header.to_string();
+        cast.push('\n');
+        for (time, kind, data) in &self.events {
+            // asciicast timestamps are seconds with microsecond precision.
+            let time = (time * 1_000_000.0).round() / 1_000_000.0;
+            cast.push_str(&json!([time, kind, data]).to_string());
+            cast.push('\n');
+        }
+        cast
+    }
+}
+
+/// Decodes `bytes` after any bytes left over from the previous call, holding
+/// back an incomplete trailing UTF-8 sequence for the next call.
+fn take_utf8(carry: &mut Vec<u8>, bytes: &[u8]) -> String {
+    carry.extend_from_slice(bytes);
+    let complete = match std::str::from_utf8(carry) {
+        Ok(_) => carry.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => carry.len(),
+    };
+    let text = String::from_utf8_lossy(&carry[..complete]).to_string();
+    carry.drain(..complete);
+    text
+}
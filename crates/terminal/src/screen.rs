@@ -0,0 +1,268 @@
+use serde_json::{json, Value};
+
+/// Each row costs `cols` cells of 32 bytes, about 3.8 MB at 120 columns.
+const SCROLLBACK_ROWS: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// One plain-text string per row.
+    Text,
+    /// One list of attribute runs per row.
+    Styled,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotOptions {
+    pub format: SnapshotFormat,
+    pub include_scrollback: bool,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        Self {
+            format: SnapshotFormat::Text,
+            include_scrollback: false,
+        }
+    }
+}
+
+/// Captures escape sequences that don't affect the grid itself.
+#[derive(Default)]
+struct ScreenCallbacks {
+    title: Option<String>,
+}
+
+impl vt100::Callbacks for ScreenCallbacks {
+    fn set_window_title(&mut self, _: &mut vt100::Screen, title: &[u8]) {
+        let title = String::from_utf8_lossy(title).trim().to_string();
+        self.title = (!title.is_empty()).then_some(title);
+    }
+}
+
+/// Headless xterm-compatible emulator state for one session, fed with the same
+/// bytes as the raw output buffer.
+pub(crate) struct ScreenModel {
+    parser: vt100::Parser<ScreenCallbacks>,
+    /// Filled scrollback rows of the primary screen, as of the last time it
+    /// was active; it cannot grow while the alternate screen is up.
+    primary_scrollback: usize,
+    /// The alternate grid is allocated on first use and then kept.
+    alternate_allocated: bool,
+}
+
+impl ScreenModel {
+    pub(crate) fn new(rows: u16, cols: u16, scrollback: Option<usize>) -> Self {
+        Self {
+            parser: vt100::Parser::new_with_callbacks(
+                rows,
+                cols,
+                scrollback.unwrap_or(SCROLLBACK_ROWS),
+                ScreenCallbacks::default(),
+            ),
+            primary_scrollback: 0,
+            alternate_allocated: false,
+        }
+    }
+
+    /// The window title last set through OSC 0 or OSC 2.
+    pub(crate) fn title(&self) -> Option<&str> {
+        self.parser.callbacks().title.as_deref()
+    }
+
+    pub(crate) fn process(&mut self, bytes: &[u8]) {
+        self.parser.process(bytes);
+        self.track_grids();
+    }
+
+    /// Approximate heap size of both grids and the primary scrollback.
+    pub(crate) fn memory_bytes(&self) -> usize {
+        let (rows, cols) = self.parser.screen().size();
+        let grids = if self.alternate_allocated { 2 } else { 1 };
+        (grids * rows as usize + self.primary_scrollback)
+            * cols as usize
+            * std::mem::size_of::<vt100::Cell>()
+    }
+
+    pub(crate) fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.screen_mut().set_size(rows, cols);
+        self.track_grids();
+    }
+
+    fn track_grids(&mut self) {
+        let screen = self.parser.screen_mut();
+        if screen.alternate_screen() {
+            self.alternate_allocated = true;
+            return;
+        }
+        // vt100 only exposes the filled scrollback length as the limit on the
+        // viewport offset, so probe it and put the viewport back.
+        let offset = screen.scrollback();
+        screen.set_scrollback(usize::MAX);
+        self.primary_scrollback = screen.scrollback();
+        screen.set_scrollback(offset);
+    }
+
+    pub(crate) fn snapshot(&mut self, options: SnapshotOptions) -> Value {
+        let scrollback = if options.include_scrollback {
+            self.scrollback_rows(options.format)
+        } else {
+            Vec::new()
+        };
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        let lines = (0..rows)
+            .map(|row| render_row(screen, row, cols, options.format))
+            .collect::<Vec<Value>>();
+        json!({
+            "rows": rows,
+            "cols": cols,
+            "cursor": {
+                "row": cursor_row,
+                "col": cursor_col,
+                "hidden": screen.hide_cursor(),
+            },
+            "alternateScreen": screen.alternate_screen(),
+            "title": self.title(),
+            "scrollback": scrollback,
+            "lines": lines,
+        })
+    }
+
+    /// Collects rows that scrolled off the top of the primary screen, oldest
+    /// first, by paging the viewport back through the scrollback.
+    fn scrollback_rows(&mut self, format: SnapshotFormat) -> Vec<Value> {
+        let screen = self.parser.screen_mut();
+        let (rows, cols) = screen.size();
+        screen.set_scrollback(usize::MAX);
+        let mut remaining = screen.scrollback();
+        let mut collected = Vec::with_capacity(remaining);
+        while remaining > 0 {
+            screen.set_scrollback(remaining);
+            let take = remaining.min(rows as usize);
+            for row in 0..take as u16 {
+                collected.push(render_row(screen, row, cols, format));
+            }
+            remaining -= take;
+        }
+        screen.set_scrollback(0);
+        collected
+    }
+}
+
+fn render_row(screen: &vt100::Screen, row: u16, cols: u16, format: SnapshotFormat) -> Value {
+    match format {
+        SnapshotFormat::Text => {
+            let mut text = String::new();
+            for col in 0..cols {
+                if let Some(cell) = screen.cell(row, col) {
+                    if cell.is_wide_continuation() {
+                        continue;
+                    }
+                    if cell.has_contents() {
+                        text.push_str(cell.contents());
+                    } else {
+                        text.push(' ');
+                    }
+                }
+            }
+            Value::String(text.trim_end().to_string())
+        }
+        SnapshotFormat::Styled => Value::Array(styled_runs(screen, row, cols)),
+    }
+}
+
+fn styled_runs(screen: &vt100::Screen, row: u16, cols: u16) -> Vec<Value> {
+    let mut runs = Vec::new();
+    let mut current: Option<(Value, String)> = None;
+    for col in 0..cols {
+        let Some(cell) = screen.cell(row, col) else {
+            continue;
+        };
+        if cell.is_wide_continuation() {
+            continue;
+        }
+        let style = json!({
+            "fg": color_value(cell.fgcolor()),
+            "bg": color_value(cell.bgcolor()),
+            "bold": cell.bold(),
+            "dim": cell.dim(),
+            "italic": cell.italic(),
+            "underline": cell.underline(),
+            "inverse": cell.inverse(),
+        });
+        let text = if cell.has_contents() {
+            cell.contents()
+        } else {
+            " "
+        };
+        match current.as_mut() {
+            Some((run_style, run_text)) if *run_style == style => run_text.push_str(text),
+            _ => {
+                if let Some(run) = current.take() {
+                    runs.push(run);
+                }
+                current = Some((style, text.to_string()));
+            }
+        }
+    }
+    runs.extend(current);
+    // Trailing unstyled blanks carry no information.
+    if let Some((style, text)) = runs.last_mut() {
+        if style["bg"].is_null() && !style["inverse"].as_bool().unwrap_or(false) {
+            let trimmed = text.trim_end().len();
+            text.truncate(trimmed);
+        }
+    }
+    if runs.last().is_some_and(|(_, text)| text.is_empty()) {
+        runs.pop();
+    }
+    runs.into_iter()
+        .map(|(mut style, text)| {
+            style["text"] = Value::String(text);
+            style
+        })
+        .collect()
+}
+
+fn color_value(color: vt100::Color) -> Value {
+    match color {
+        vt100::Color::Default => Value::Null,
+        vt100::Color::Idx(index) => json!(index),
+        vt100::Color::Rgb(r, g, b) => json!(format!("#{r:02x}{g:02x}{b:02x}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL: usize = std::mem::size_of::<vt100::Cell>();
+
+    #[test]
+    fn primary_scrollback_is_counted_while_the_alternate_screen_is_up() {
+        let mut screen = ScreenModel::new(24, 80, Some(100));
+        assert_eq!(screen.memory_bytes(), 24 * 80 * CELL);
+
+        for line in 0..74 {
+            screen.process(format!("line {line}\r\n").as_bytes());
+        }
+        assert_eq!(screen.memory_bytes(), (24 + 51) * 80 * CELL);
+
+        screen.process(b"\x1b[?1049h");
+        assert_eq!(screen.memory_bytes(), (2 * 24 + 51) * 80 * CELL);
+        screen.process(b"\x1b[?1049l");
+        assert_eq!(screen.memory_bytes(), (2 * 24 + 51) * 80 * CELL);
+    }
+
+    #[test]
+    fn measuring_keeps_the_viewport_offset() {
+        let mut screen = ScreenModel::new(4, 20, None);
+        for line in 0..10 {
+            screen.process(format!("line {line}\r\n").as_bytes());
+        }
+        screen.parser.screen_mut().set_scrollback(3);
+        screen.process(b"");
+        assert_eq!(screen.parser.screen().scrollback(), 3);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::ansi::strip_ansi_mapped;
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use serde_json::{json, Value};
+
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Parameters for [`crate::TerminalManager::search`].
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub query: String,
+    /// Treats `query` as a regular expression instead of a literal string.
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Lines of context returned before and after each matching line.
+    pub context_lines: usize,
+    /// Upper bound on the number of matches returned across all sessions.
+    pub max_results: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+            regex: false,
+            case_sensitive: false,
+            context_lines: 2,
+            max_results: 200,
+        }
+    }
+}
+
+impl SearchOptions {
+    pub(crate) fn compile(&self) -> Result<Regex> {
+        if self.query.is_empty() {
+            return Err(anyhow!("search query cannot be empty"));
+        }
+        let pattern = if self.regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|err| anyhow!("invalid search pattern: {err}"))
+    }
+}
+
+/// Searches the ANSI-stripped text of `bytes`, which start at absolute offset
+/// `base`, line by line. Each match reports its 0-based line within the
+/// retained buffer, its character column and the raw `[offset, endOffset)`
+/// byte range in the session's output stream. Stops after `limit` matches.
+pub(crate) fn search_output(
+    session_id: &str,
+    bytes: &[u8],
+    base: u64,
+    pattern: &Regex,
+    context_lines: usize,
+    limit: usize,
+) -> Vec<Value> {
+    let (text, map) = strip_ansi_mapped(bytes, base);
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in text.split('\n') {
+        lines.push((start, line));
+        start += line.len() + 1;
+    }
+    let mut matches = Vec::new();
+    for (index, (line_start, line)) in lines.iter().enumerate() {
+        for found in pattern.find_iter(line) {
+            if matches.len() >= limit {
+                return matches;
+            }
+            if found.is_empty() {
+                continue;
+            }
+            let offset = map[line_start + found.start()];
+            let end_offset = map[line_start + found.end() - 1] + 1;
+            let context = |range: std::ops::Range<usize>| {
+                lines[range]
+                    .iter()
+                    .map(|(_, line)| line.to_string())
+                    .collect::<Vec<_>>()
+            };
+            matches.push(json!({
+                "sessionId": session_id,
+                "line": index,
+                "column": line[..found.start()].chars().count(),
+                "text": line,
+                "match": found.as_str(),
+                "before": context(index.saturating_sub(context_lines)..index),
+                "after": context(index + 1..(index + 1 + context_lines).min(lines.len())),
+                "offset": offset,
+                "endOffset": end_offset,
+            }));
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(bytes: &[u8], options: SearchOptions) -> Vec<Value> {
+        let pattern = options.compile().unwrap();
+        search_output("s1", bytes, 1000, &pattern, options.context_lines, 10)
+    }
+
+    #[test]
+    fn reports_lines_columns_and_raw_offsets() {
+        let bytes = "one\r\n\x1b[32mtwo\x1b[0m é needle\r\nthree\r\n".as_bytes();
+        let matches = search(
+            bytes,
+            SearchOptions {
+                query: "NEEDLE".to_string(),
+                context_lines: 1,
+                ..SearchOptions::default()
+            },
+        );
+        let [found] = matches.as_slice() else {
+            panic!("expected one match: {matches:?}");
+        };
+        assert_eq!(found["line"], 1);
+        assert_eq!(found["column"], 6);
+        assert_eq!(found["text"], "two é needle");
+        assert_eq!(found["before"], json!(["one"]));
+        assert_eq!(found["after"], json!(["three"]));
+        let start = found["offset"].as_u64().unwrap() - 1000;
+        let end = found["endOffset"].as_u64().unwrap() - 1000;
+        assert_eq!(&bytes[start as usize..end as usize], b"needle");
+    }
+
+    #[test]
+    fn honours_case_and_regex_options() {
+        let bytes = b"Error: 1\nerror: 22\n";
+        let literal = SearchOptions {
+            query: "error: 2".to_string(),
+            case_sensitive: true,
+            ..SearchOptions::default()
+        };
+        assert_eq!(search(bytes, literal).len(), 1);
+        let regex = SearchOptions {
+            query: r"error: \d+".to_string(),
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let matches = search(bytes, regex);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1]["match"], "error: 22");
+        assert!(SearchOptions::default().compile().is_err());
+        let invalid = SearchOptions {
+            query: "(".to_string(),
+            regex: true,
+            ..SearchOptions::default()
+        };
+        assert!(invalid.compile().is_err());
+    }
+}
@@ -0,0 +1,411 @@
+use crate::ansi::strip_ansi;
+use crate::buffer::OutputBuffer;
+use anyhow::Result;
+use host_api::TerminalCommand;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_OSC_BYTES: usize = 4096;
+const MAX_TRACKED_COMMANDS: usize = 500;
+
+/// Bash rc file that sources the user's `~/.bashrc` and then wraps the prompt
+/// with OSC 133 markers and reports the working directory through OSC 7.
+const BASH_INTEGRATION: &str = r#"if [ -f "$HOME/.bashrc" ]; then . "$HOME/.bashrc"; fi
+__codex_urlencode() {
+  local LC_ALL=C value=$1 encoded= char index
+  for (( index = 0; index < ${#value}; index++ )); do
+    char=${value:index:1}
+    case $char in
+      [A-Za-z0-9/._~-]) encoded+=$char ;;
+      *) printf -v char '%%%02X' "'$char"; encoded+=$char ;;
+    esac
+  done
+  printf '%s' "$encoded"
+}
+__codex_precmd() {
+  local status=$?
+  printf '\033]133;D;%s\007\033]7;file://%s%s\007' "$status" "$HOSTNAME" "$(__codex_urlencode "$PWD")"
+  return $status
+}
+PROMPT_COMMAND="__codex_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+PS1="\[\033]133;A\007\]$PS1\[\033]133;B\007\]"
+PS0="\033]133;C\007$PS0"
+"#;
+
+/// Writes the bash integration script into `dir`, which is created readable
+/// by the current user only, and returns its path. The script is sourced by
+/// every bash session, so nobody else may be able to replace it.
+pub(crate) fn write_bash_rcfile(dir: &Path) -> Result<PathBuf> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        builder.mode(0o700);
+        builder.create(dir)?;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    #[cfg(not(unix))]
+    builder.create(dir)?;
+
+    let path = dir.join("bash-integration.sh");
+    let _ = std::fs::remove_file(&path);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)?
+        .write_all(BASH_INTEGRATION.as_bytes())?;
+    Ok(path)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Marker {
+    PromptStart,
+    CommandStart,
+    CommandExecuted,
+    CommandFinished(Option<i32>),
+    CommandLine(String),
+    Cwd(String),
+}
+
+/// A recognised marker with the absolute offsets of its escape sequence.
+#[derive(Debug, Clone)]
+pub(crate) struct MarkerHit {
+    pub start: u64,
+    pub end: u64,
+    pub marker: Marker,
+}
+
+#[derive(Default)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// Incremental OSC scanner; sequences may be split across output chunks.
+#[derive(Default)]
+pub(crate) struct MarkerScanner {
+    state: ScanState,
+    start: u64,
+    payload: Vec<u8>,
+}
+
+impl MarkerScanner {
+    pub(crate) fn scan(&mut self, base: u64, bytes: &[u8]) -> Vec<MarkerHit> {
+        let mut hits = Vec::new();
+        for (index, &byte) in bytes.iter().enumerate() {
+            let offset = base + index as u64;
+            match self.state {
+                ScanState::Ground => {
+                    if byte == 0x1b {
+                        self.state = ScanState::Escape;
+                        self.start = offset;
+                    }
+                }
+                ScanState::Escape => {
+                    if byte == b']' {
+                        self.state = ScanState::Osc;
+                        self.payload.clear();
+                    } else if byte == 0x1b {
+                        self.start = offset;
+                    } else {
+                        self.state = ScanState::Ground;
+                    }
+                }
+                ScanState::Osc => match byte {
+                    0x07 => {
+                        self.finish(offset + 1, &mut hits);
+                    }
+                    0x1b => self.state = ScanState::OscEscape,
+                    _ => {
+                        if self.payload.len() < MAX_OSC_BYTES {
+                            self.payload.push(byte);
+                        }
+                    }
+                },
+                ScanState::OscEscape => {
+                    if byte == b'\\' {
+                        self.finish(offset + 1, &mut hits);
+                    } else {
+                        self.state = ScanState::Ground;
+                    }
+                }
+            }
+        }
+        hits
+    }
+
+    fn finish(&mut self, end: u64, hits: &mut Vec<MarkerHit>) {
+        self.state = ScanState::Ground;
+        if let Some(marker) = parse_marker(&self.payload) {
+            hits.push(MarkerHit {
+                start: self.start,
+                end,
+                marker,
+            });
+        }
+    }
+}
+
+fn parse_marker(payload: &[u8]) -> Option<Marker> {
+    let payload = String::from_utf8_lossy(payload);
+    let mut parts = payload.splitn(3, ';');
+    let code = parts.next()?;
+    let kind = parts.next().unwrap_or_default();
+    let rest = parts.next();
+    match (code, kind) {
+        ("7", _) => {
+            let uri = payload.strip_prefix("7;")?;
+            file_uri_path(uri).map(Marker::Cwd)
+        }
+        ("133" | "633", "A") => Some(Marker::PromptStart),
+        ("133" | "633", "B") => Some(Marker::CommandStart),
+        ("133" | "633", "C") => Some(Marker::CommandExecuted),
+        ("133" | "633", "D") => Some(Marker::CommandFinished(
+            rest.and_then(|value| value.split(';').next())
+                .and_then(|value| value.trim().parse().ok()),
+        )),
+        ("633", "E") => {
+            let command = rest?.split(';').next().unwrap_or_default();
+            Some(Marker::CommandLine(unescape_633(command)))
+        }
+        ("633", "P") => rest?
+            .strip_prefix("Cwd=")
+            .map(|cwd| Marker::Cwd(unescape_633(cwd))),
+        _ => None,
+    }
+}
+
+/// Strips `file://host` from an OSC 7 URI and percent-decodes the path.
+fn file_uri_path(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            if let Some(byte) = hex_byte(bytes.get(index + 1..index + 3)) {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    Some(String::from_utf8_lossy(&decoded).to_string())
+}
+
+/// OSC 633 escapes `\` as `\\` and other bytes as `\xAB`.
+fn unescape_633(value: &str) -> String {
+    let mut decoded = Vec::with_capacity(value.len());
+    let bytes = value.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'\\' {
+            if bytes.get(index + 1) == Some(&b'\\') {
+                decoded.push(b'\\');
+                index += 2;
+                continue;
+            }
+            if bytes.get(index + 1) == Some(&b'x') {
+                if let Some(byte) = hex_byte(bytes.get(index + 2..index + 4)) {
+                    decoded.push(byte);
+                    index += 4;
+                    continue;
+                }
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn hex_byte(digits: Option<&[u8]>) -> Option<u8> {
+    let digits = std::str::from_utf8(digits?).ok()?;
+    u8::from_str_radix(digits, 16).ok()
+}
+
+/// Folds markers into a bounded history of executed commands.
+pub(crate) struct CommandTracker {
+    commands: VecDeque<TerminalCommand>,
+    running: Option<TerminalCommand>,
+    next_index: u64,
+    cwd: Option<String>,
+    prompt_offset: Option<u64>,
+    input_offset: Option<u64>,
+    command_line: Option<String>,
+}
+
+impl CommandTracker {
+    pub(crate) fn new(cwd: String) -> Self {
+        Self {
+            commands: VecDeque::new(),
+            running: None,
+            next_index: 0,
+            cwd: Some(cwd),
+            prompt_offset: None,
+            input_offset: None,
+            command_line: None,
+        }
+    }
+
+    pub(crate) fn apply(&mut self, hit: MarkerHit, buffer: &OutputBuffer) {
+        match hit.marker {
+            Marker::PromptStart => {
+                self.finish(hit.start, None);
+                self.prompt_offset = Some(hit.start);
+                self.input_offset = None;
+            }
+            Marker::CommandStart => self.input_offset = Some(hit.end),
+            Marker::CommandLine(command) => self.command_line = Some(command),
+            Marker::Cwd(cwd) => self.cwd = Some(cwd),
+            Marker::CommandExecuted => {
+                self.finish(hit.start, None);
+                let command = self
+                    .command_line
+                    .take()
+                    .or_else(|| {
+                        let from = self.input_offset?;
+                        buffer
+                            .range(from, hit.start)
+                            .map(|bytes| strip_ansi(&bytes))
+                    })
+                    .map(|command| command.trim().to_string())
+                    .filter(|command| !command.is_empty());
+                self.running = Some(TerminalCommand {
+                    index: self.next_index,
+                    command,
+                    cwd: self.cwd.clone(),
+                    started_at_ms: now_ms(),
+                    finished_at_ms: None,
+                    exit_code: None,
+                    prompt_offset: self.prompt_offset.take(),
+                    output_start: hit.end,
+                    output_end: None,
+                });
+                self.next_index += 1;
+                self.input_offset = None;
+            }
+            Marker::CommandFinished(exit_code) => self.finish(hit.start, exit_code),
+        }
+    }
+
+    fn finish(&mut self, end: u64, exit_code: Option<i32>) {
+        let Some(mut command) = self.running.take() else {
+            return;
+        };
+        command.finished_at_ms = Some(now_ms());
+        command.output_end = Some(end);
+        command.exit_code = exit_code;
+        self.commands.push_back(command);
+        while self.commands.len() > MAX_TRACKED_COMMANDS {
+            self.commands.pop_front();
+        }
+    }
+
+    /// Finished commands oldest first, followed by the running one if any.
+    pub(crate) fn commands(&self) -> Vec<TerminalCommand> {
+        self.commands
+            .iter()
+            .chain(self.running.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::MemoryBudget;
+    use std::sync::Arc;
+
+    fn markers(chunks: &[&[u8]]) -> Vec<(u64, u64, Marker)> {
+        let mut scanner = MarkerScanner::default();
+        let mut base = 0;
+        let mut hits = Vec::new();
+        for chunk in chunks {
+            hits.extend(
+                scanner
+                    .scan(base, chunk)
+                    .into_iter()
+                    .map(|hit| (hit.start, hit.end, hit.marker)),
+            );
+            base += chunk.len() as u64;
+        }
+        hits
+    }
+
+    #[test]
+    fn scans_markers_split_across_chunks() {
+        let hits = markers(&[b"ab\x1b]13", b"3;A\x07$ \x1b]133;B\x1b", b"\\ls"]);
+        assert_eq!(
+            hits,
+            [(2, 10, Marker::PromptStart), (12, 21, Marker::CommandStart)]
+        );
+        // Other OSC sequences and stray escapes are skipped.
+        assert!(markers(&[b"\x1b]0;title\x07\x1b[31m\x1bx"]).is_empty());
+    }
+
+    #[test]
+    fn parses_osc_133_633_and_7() {
+        let parse = |payload: &str| parse_marker(payload.as_bytes());
+        assert_eq!(parse("133;C"), Some(Marker::CommandExecuted));
+        assert_eq!(parse("133;D;2"), Some(Marker::CommandFinished(Some(2))));
+        assert_eq!(parse("633;D"), Some(Marker::CommandFinished(None)));
+        assert_eq!(
+            parse(r"633;E;echo a\x3bb \\ c;nonce"),
+            Some(Marker::CommandLine(r"echo a;b \ c".to_string()))
+        );
+        assert_eq!(
+            parse("633;P;Cwd=/tmp/x"),
+            Some(Marker::Cwd("/tmp/x".to_string()))
+        );
+        assert_eq!(
+            parse("7;file://host/tmp/a%20b%25c/%C3%A9"),
+            Some(Marker::Cwd("/tmp/a b%c/é".to_string()))
+        );
+        assert_eq!(parse("7;/tmp"), None);
+        assert_eq!(parse("633;P;Other=1"), None);
+        assert_eq!(parse("0;title"), None);
+    }
+
+    #[test]
+    fn tracks_commands_between_markers() {
+        let stream = b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07out\r\n\x1b]133;D;1\x07\x1b]133;A\x07$ ";
+        let mut buffer = OutputBuffer::new(None, Arc::new(MemoryBudget::new(usize::MAX)));
+        buffer.push(stream);
+        let mut tracker = CommandTracker::new("/start".to_string());
+        for hit in MarkerScanner::default().scan(0, stream) {
+            tracker.apply(hit, &buffer);
+        }
+        let [command] = tracker.commands().try_into().unwrap_or_else(|commands| {
+            panic!("expected one command: {commands:?}");
+        });
+        assert_eq!(command.command.as_deref(), Some("ls"));
+        assert_eq!(command.cwd.as_deref(), Some("/start"));
+        assert_eq!(command.exit_code, Some(1));
+        assert_eq!(command.prompt_offset, Some(0));
+        let output = buffer
+            .range(command.output_start, command.output_end.unwrap())
+            .unwrap();
+        assert_eq!(&*output, b"out\r\n");
+    }
+}